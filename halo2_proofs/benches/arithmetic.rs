@@ -11,6 +11,77 @@ use halo2_proofs::poly::{commitment::ParamsProver, ipa::commitment::ParamsIPA};
 use criterion::{black_box, Criterion};
 use rand_core::OsRng;
 
+/// Size sweeps over the core arithmetic routines, on inputs from
+/// `halo2_proofs::testing` drawn with a fixed seed, so that numbers quoted for
+/// a change can be reproduced by running this benchmark before and after it,
+/// e.g. with `--save-baseline` and `--baseline`.
+#[cfg(feature = "testing")]
+fn sweep_benchmark(c: &mut Criterion) {
+    use crate::arithmetic::{
+        best_fft, best_multiexp_cpu, eval_polynomial, kate_division, lagrange_interpolate,
+        root_of_unity,
+    };
+    use criterion::BenchmarkId;
+    use halo2_proofs::testing::{random_bases, random_scalars};
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+    let mut group = c.benchmark_group("sweep_fft");
+    for k in [10, 14, 18] {
+        let mut a = random_scalars::<Fp>(1 << k, &mut rng);
+        let omega = root_of_unity::<Fp>(k);
+        group.bench_function(BenchmarkId::new("k", k), |b| {
+            b.iter(|| best_fft(&mut a, omega, k));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("sweep_multiexp");
+    group.sample_size(10);
+    for k in [10, 14, 18] {
+        let coeffs = random_scalars::<Fp>(1 << k, &mut rng);
+        let bases = random_bases::<EqAffine>(1 << k, &mut rng);
+        group.bench_function(BenchmarkId::new("k", k), |b| {
+            b.iter(|| best_multiexp_cpu(&coeffs, &bases));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("sweep_eval_polynomial");
+    for k in [10, 14, 18] {
+        let poly = random_scalars::<Fp>(1 << k, &mut rng);
+        let point = Fp::random(&mut rng);
+        group.bench_function(BenchmarkId::new("k", k), |b| {
+            b.iter(|| eval_polynomial(&poly, point));
+        });
+    }
+    group.finish();
+
+    // Interpolation is quadratic in the number of points.
+    let mut group = c.benchmark_group("sweep_lagrange_interpolate");
+    group.sample_size(10);
+    for k in [6, 8, 10] {
+        let points = random_scalars::<Fp>(1 << k, &mut rng);
+        let evals = random_scalars::<Fp>(1 << k, &mut rng);
+        group.bench_function(BenchmarkId::new("k", k), |b| {
+            b.iter(|| lagrange_interpolate(&points, &evals));
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("sweep_kate_division");
+    for k in [10, 14, 18] {
+        let poly = random_scalars::<Fp>(1 << k, &mut rng);
+        let root = Fp::random(&mut rng);
+        group.bench_function(BenchmarkId::new("k", k), |b| {
+            b.iter(|| kate_division(&poly, root));
+        });
+    }
+    group.finish();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let rng = OsRng;
 
@@ -34,5 +105,8 @@ fn criterion_benchmark(c: &mut Criterion) {
     }
 }
 
+#[cfg(not(feature = "testing"))]
 criterion_group!(benches, criterion_benchmark);
+#[cfg(feature = "testing")]
+criterion_group!(benches, criterion_benchmark, sweep_benchmark);
 criterion_main!(benches);
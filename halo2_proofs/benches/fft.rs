@@ -1,17 +1,18 @@
 #[macro_use]
 extern crate criterion;
 
-use crate::arithmetic::best_fft;
+use crate::arithmetic::{best_fft, best_fft_radix4, fft_group, root_of_unity, truncated_fft};
 use group::ff::Field;
+use group::Group;
 use halo2_proofs::*;
-use halo2curves::pasta::Fp;
+use halo2curves::pasta::{Eq, EqAffine, Fp};
 
-use criterion::{BenchmarkId, Criterion};
+use criterion::{BatchSize, BenchmarkId, Criterion};
 use rand_core::OsRng;
 
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("fft");
-    for k in 3..8 {
+    for k in (3..8).chain(std::iter::once(18)) {
         group.bench_function(BenchmarkId::new("k", k), |b| {
             let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
             let omega = Fp::random(OsRng); // would be weird if this mattered
@@ -20,6 +21,62 @@ fn criterion_benchmark(c: &mut Criterion) {
             });
         });
     }
+    group.finish();
+
+    let mut group = c.benchmark_group("fft_radix4");
+    for k in [16, 18, 20] {
+        let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let omega = Fp::random(OsRng);
+        group.bench_function(BenchmarkId::new("radix2", k), |b| {
+            b.iter(|| best_fft(&mut a, omega, k));
+        });
+        group.bench_function(BenchmarkId::new("radix4", k), |b| {
+            b.iter(|| best_fft_radix4(&mut a, omega, k));
+        });
+    }
+    group.finish();
+
+    // Low-degree polynomials in a large domain, with 1/8 of the coefficients
+    // nonzero. Each iteration transforms a fresh copy, as the transform
+    // overwrites the zero coefficients.
+    let mut group = c.benchmark_group("fft_truncated");
+    for k in [16, 18, 20] {
+        let n = 1 << k;
+        let a = (0..n)
+            .map(|i| if i < n / 8 { Fp::random(OsRng) } else { Fp::ZERO })
+            .collect::<Vec<_>>();
+        let omega = Fp::random(OsRng);
+        group.bench_function(BenchmarkId::new("full", k), |b| {
+            b.iter_batched(
+                || a.clone(),
+                |mut a| best_fft(&mut a, omega, k),
+                BatchSize::LargeInput,
+            );
+        });
+        group.bench_function(BenchmarkId::new("truncated", k), |b| {
+            b.iter_batched(
+                || a.clone(),
+                |mut a| truncated_fft(&mut a, omega, k, n / 8),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+
+    // FFTs over curve points, as in `g_to_lagrange`.
+    let mut group = c.benchmark_group("fft_group");
+    group.sample_size(10);
+    for k in [10, 18] {
+        let mut a = (0..(1 << k)).map(|_| Eq::random(OsRng)).collect::<Vec<_>>();
+        let omega = root_of_unity::<Fp>(k);
+        group.bench_function(BenchmarkId::new("generic", k), |b| {
+            b.iter(|| best_fft(&mut a, omega, k));
+        });
+        group.bench_function(BenchmarkId::new("specialized", k), |b| {
+            b.iter(|| fft_group::<EqAffine>(&mut a, omega, k));
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,72 @@
+#[macro_use]
+extern crate criterion;
+
+use crate::arithmetic::{
+    best_multiexp_cpu, multiexp_serial_const_pub, multiexp_serial_pub, multiexp_serial_soa_pub,
+};
+use group::ff::Field;
+use group::{prime::PrimeCurveAffine, Curve, Group};
+use halo2_proofs::*;
+use halo2curves::pasta::{Eq, EqAffine, Fp};
+
+use criterion::{BenchmarkId, Criterion};
+use rand_core::OsRng;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multiexp_serial");
+    group.sample_size(10);
+    for k in 16..=20 {
+        let n = 1 << k;
+        let coeffs = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let g = Eq::random(OsRng);
+        let points = (0..n)
+            .scan(g, |acc, _| {
+                *acc += g;
+                Some(*acc)
+            })
+            .collect::<Vec<_>>();
+        let mut bases = vec![EqAffine::identity(); n];
+        Eq::batch_normalize(&points, &mut bases);
+
+        group.bench_function(BenchmarkId::new("enum", k), |b| {
+            b.iter(|| multiexp_serial_pub(&coeffs, &bases));
+        });
+        group.bench_function(BenchmarkId::new("soa", k), |b| {
+            b.iter(|| multiexp_serial_soa_pub(&coeffs, &bases));
+        });
+    }
+    group.finish();
+
+    // For 2^17 bases, `multiexp_serial_pub` picks a 12-bit window at runtime.
+    let mut group = c.benchmark_group("multiexp_serial_window");
+    group.sample_size(10);
+    let k = 17;
+    let coeffs = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..(1 << k))
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<_>>();
+    group.bench_function(BenchmarkId::new("runtime", 12), |b| {
+        b.iter(|| multiexp_serial_pub(&coeffs, &bases));
+    });
+    group.bench_function(BenchmarkId::new("const", 12), |b| {
+        b.iter(|| multiexp_serial_const_pub::<EqAffine, 12>(&coeffs, &bases));
+    });
+    group.finish();
+
+    // Compare runs with and without the `fast-msm` feature, e.g. with
+    // `--save-baseline` and `--baseline`, to measure the bucket bounds checks.
+    let mut group = c.benchmark_group("multiexp");
+    group.sample_size(10);
+    let k = 18;
+    let coeffs = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..(1 << k))
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<_>>();
+    group.bench_function(BenchmarkId::new("k", k), |b| {
+        b.iter(|| best_multiexp_cpu(&coeffs, &bases));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
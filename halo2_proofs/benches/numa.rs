@@ -0,0 +1,92 @@
+#[macro_use]
+extern crate criterion;
+
+use crate::arithmetic::{multiexp_serial_pub, parallelize, parallelize_numa};
+use group::ff::Field;
+use group::{prime::PrimeCurveAffine, Curve, Group};
+use halo2_proofs::*;
+use halo2curves::pasta::{Eq, EqAffine, Fp};
+use std::sync::Mutex;
+
+use criterion::{BenchmarkId, Criterion};
+use rand_core::OsRng;
+
+/// Normalizes 2^22 bases, as done before an MSM of that size, then performs the
+/// MSM itself. Each variant has its own buffers. With `parallelize_numa`, every
+/// region of the buffers is always processed by the same node, so that its
+/// pages migrate to that node under the kernel's automatic NUMA balancing; with
+/// `parallelize`, which thread processes which chunk changes from one
+/// iteration to the next.
+fn criterion_benchmark(c: &mut Criterion) {
+    let k = 22;
+    let n = 1 << k;
+    let g = Eq::random(OsRng);
+
+    let mut group = c.benchmark_group("msm_preprocessing");
+    group.sample_size(10);
+
+    let points = vec![g; n];
+    let mut bases = vec![EqAffine::identity(); n];
+    group.bench_function(BenchmarkId::new("uniform", k), |b| {
+        b.iter(|| {
+            parallelize(&mut bases, |bases, start| {
+                Eq::batch_normalize(&points[start..(start + bases.len())], bases)
+            })
+        });
+    });
+
+    let points = vec![g; n];
+    let mut bases = vec![EqAffine::identity(); n];
+    group.bench_function(BenchmarkId::new("numa", k), |b| {
+        b.iter(|| {
+            parallelize_numa(&mut bases, |bases, start| {
+                Eq::batch_normalize(&points[start..(start + bases.len())], bases)
+            })
+        });
+    });
+    group.finish();
+
+    // An MSM of size 2^22, as chunks of serial MSMs whose results are summed,
+    // so that only the placement of the chunks differs between the variants.
+    let mut group = c.benchmark_group("msm");
+    group.sample_size(10);
+
+    let coeffs = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let points = (0..n)
+        .scan(g, |acc, _| {
+            *acc += g;
+            Some(*acc)
+        })
+        .collect::<Vec<_>>();
+    let mut bases = vec![EqAffine::identity(); n];
+    Eq::batch_normalize(&points, &mut bases);
+    drop(points);
+
+    let mut uniform_bases = bases.clone();
+    group.bench_function(BenchmarkId::new("uniform", k), |b| {
+        b.iter(|| {
+            let acc = Mutex::new(Eq::identity());
+            parallelize(&mut uniform_bases, |bases, start| {
+                let part = multiexp_serial_pub(&coeffs[start..(start + bases.len())], bases);
+                *acc.lock().unwrap() += part;
+            });
+            acc.into_inner().unwrap()
+        });
+    });
+
+    let mut numa_bases = bases;
+    group.bench_function(BenchmarkId::new("numa", k), |b| {
+        b.iter(|| {
+            let acc = Mutex::new(Eq::identity());
+            parallelize_numa(&mut numa_bases, |bases, start| {
+                let part = multiexp_serial_pub(&coeffs[start..(start + bases.len())], bases);
+                *acc.lock().unwrap() += part;
+            });
+            acc.into_inner().unwrap()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
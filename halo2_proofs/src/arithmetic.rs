@@ -1,32 +1,196 @@
 //! This module provides common utilities, traits and structures for group,
 //! field and polynomial arithmetic.
+//!
+//! # GPU dispatch
+//!
+//! The only GPU backend is Icicle, behind the `icicle_gpu` feature, so there
+//! is no precedence between GPU backends to settle. With `icicle_gpu`,
+//! [`best_multiexp`] copies its `bases` to the GPU and runs there, for curves
+//! the GPU supports and inputs above the `ICICLE_SMALL_K` threshold, falling
+//! back to [`best_multiexp_cpu`] otherwise or if the GPU fails. Without it,
+//! [`best_multiexp`] is [`best_multiexp_cpu`]. `best_multiexp_gpu_or_cpu`
+//! instead runs over bases copied to the device ahead of time, as the KZG
+//! commitment scheme does. The `best_*_cpu` functions and [`best_fft`] always
+//! run on the CPU; there is no GPU FFT.
 
 use super::multicore;
+pub use super::multicore::{ThreadPool, ThreadPoolBuilder};
 pub use ff::Field;
 use group::{
     ff::{BatchInvert, PrimeField},
-    Curve, Group, GroupOpsOwned, ScalarMulOwned,
+    Curve, Group, GroupEncoding, GroupOpsOwned, ScalarMulOwned,
 };
 pub use halo2curves::{CurveAffine, CurveExt};
-use std::time::Instant;
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+use std::time::{Duration, Instant};
+#[cfg(feature = "profiling")]
 use std::error::Error;
 #[cfg(feature = "icicle_gpu")]
 use super::icicle;
 #[cfg(feature = "icicle_gpu")]
 use rustacuda::prelude::DeviceBuffer;
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
 use csv::Writer;
-use std::path::Path;
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+use std::fs::File;
+#[cfg(feature = "profiling")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "profiling")]
 use serde::Serialize;
 
+pub mod serial;
+pub use serial::{bitreverse, bitreverse_permute, kate_division, lagrange_interpolate};
 
+#[cfg(feature = "mmap")]
+#[allow(unsafe_code)]
+mod mmap;
+#[cfg(feature = "mmap")]
+pub use mmap::fft_mmap;
+#[cfg(feature = "numa")]
+mod numa;
+#[cfg(feature = "simd-msm")]
+mod batch_affine;
+
+#[cfg(feature = "profiling")]
+static PROFILING_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "profiling")]
+static PROFILING_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+#[cfg(feature = "profiling")]
+static PROFILING_LABEL: Mutex<Option<String>> = Mutex::new(None);
+#[cfg(feature = "profiling")]
+static PROFILING_ERROR_HANDLER: Mutex<Option<Arc<ProfilingErrorHandler>>> = Mutex::new(None);
+#[cfg(feature = "profiling")]
+static PROFILING_HISTOGRAM: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "profiling")]
+static PROFILING_SUMMARIES: Mutex<Option<HashMap<SummaryKey, OpSummary>>> = Mutex::new(None);
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+static PROFILING_WRITERS: Mutex<Option<HashMap<PathBuf, Writer<File>>>> = Mutex::new(None);
+
+/// A callback receiving the errors encountered while writing profiling files.
+#[cfg(feature = "profiling")]
+pub type ProfilingErrorHandler = dyn Fn(ProfilingError) + Send + Sync;
+
+/// An error encountered while writing FFT or MSM timings to the profiling
+/// files. These are passed to the handler set with
+/// [`set_profiling_error_handler`], and never interrupt the operation being
+/// profiled.
+#[cfg(feature = "profiling")]
+#[derive(Debug)]
+pub enum ProfilingError {
+    /// The timing of an FFT could not be written.
+    Fft(Box<dyn Error + Send + Sync>),
+    /// The timing of an MSM could not be written.
+    Msm(Box<dyn Error + Send + Sync>),
+    /// The aggregated timings could not be written by [`flush_profiling`].
+    Summary(Box<dyn Error + Send + Sync>),
+}
+
+#[cfg(feature = "profiling")]
+impl std::fmt::Display for ProfilingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfilingError::Fft(e) => write!(f, "failed to log FFT stats: {}", e),
+            ProfilingError::Msm(e) => write!(f, "failed to log MSM stats: {}", e),
+            ProfilingError::Summary(e) => write!(f, "failed to log profiling summary: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Error for ProfilingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProfilingError::Fft(e) | ProfilingError::Msm(e) | ProfilingError::Summary(e) => {
+                Some(e.as_ref())
+            }
+        }
+    }
+}
+
+/// Sets the handler called with each error encountered while writing the
+/// profiling files, e.g. when the disk is full. By default these errors are
+/// silently dropped.
+#[cfg(feature = "profiling")]
+pub fn set_profiling_error_handler(handler: Box<ProfilingErrorHandler>) {
+    *PROFILING_ERROR_HANDLER.lock().unwrap() = Some(Arc::from(handler));
+}
+
+#[cfg(feature = "profiling")]
+fn report_profiling_error(error: ProfilingError) {
+    // The handler is called without holding the lock, so that it may replace
+    // itself.
+    let handler = PROFILING_ERROR_HANDLER.lock().unwrap().clone();
+    if let Some(handler) = handler {
+        handler(error);
+    }
+}
+
+/// Enables or disables logging of FFT and MSM timings to CSV files, or to
+/// newline-delimited JSON files with the `profiling-json` feature.
+///
+/// Profiling is disabled by default, in which case no files are touched and
+/// no logging records are built.
+#[cfg(feature = "profiling")]
+pub fn set_profiling_enabled(enabled: bool) {
+    PROFILING_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Sets the directory into which the profiling CSV files are written. If this
+/// is never called, the files are written to the current working directory.
+#[cfg(feature = "profiling")]
+pub fn set_profiling_path(path: PathBuf) {
+    *PROFILING_PATH.lock().unwrap() = Some(path);
+}
+
+/// Tags the rows subsequently written to the profiling CSV files with `label`,
+/// e.g. to tell apart the FFTs of different proving phases.
+#[cfg(feature = "profiling")]
+pub fn set_profiling_label(label: &str) {
+    *PROFILING_LABEL.lock().unwrap() = Some(label.to_string());
+}
+
+/// Stops tagging the rows written to the profiling CSV files, whose label
+/// column is then left empty.
+#[cfg(feature = "profiling")]
+pub fn clear_profiling_label() {
+    *PROFILING_LABEL.lock().unwrap() = None;
+}
+
+#[cfg(feature = "profiling")]
+fn profiling_label() -> Option<String> {
+    PROFILING_LABEL.lock().unwrap().clone()
+}
+
+#[cfg(feature = "profiling")]
+fn profiling_enabled() -> bool {
+    PROFILING_ENABLED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "profiling")]
+fn profiling_file(filename: &str) -> PathBuf {
+    match PROFILING_PATH.lock().unwrap().as_ref() {
+        Some(dir) => dir.join(filename),
+        None => PathBuf::from(filename),
+    }
+}
+
+
+#[cfg(feature = "profiling")]
 #[derive(Serialize, Debug)]
 struct FFTLoggingInfo {     
     size: u32,
     logn: u32,
     fft_duration: f64,
     fft_type: String,
+    label: Option<String>,
 }
 
+#[cfg(feature = "profiling")]
 impl FFTLoggingInfo {
     // Constructor for FFTLoggingInfo
     fn new(size: u32, logn: u32, fft_duration: f64, fft_type: &str) -> Self {
@@ -35,127 +199,571 @@ impl FFTLoggingInfo {
             logn,
             fft_duration,
             fft_type: fft_type.to_string(),
+            label: profiling_label(),
         }
     }
 }
+#[cfg(feature = "profiling")]
 #[derive(Serialize, Debug)]
 struct MSMLoggingInfo {     
     num_coeffs: String,
     msm_duration: String,
+    label: Option<String>,
+}
+
+/// Writes rows to the profiling CSV file `filename` with `write_rows`, starting
+/// the file with `header` if it is empty.
+///
+/// Each file is opened once, on its first write, and kept open for the rest of
+/// the process rather than reopened for every row. A file that has since been
+/// removed, e.g. by log rotation, is recreated with its header. The writers
+/// stay locked until the rows are flushed, so that the rows of concurrent
+/// operations are never interleaved.
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+fn write_profiling_csv(
+    filename: &str,
+    header: &[&str],
+    write_rows: impl FnOnce(&mut Writer<File>) -> csv::Result<()>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    use std::collections::hash_map::Entry;
+
+    let path = profiling_file(filename);
+    let mut writers = PROFILING_WRITERS.lock().unwrap();
+    let writers = writers.get_or_insert_with(HashMap::new);
+    if !path.exists() {
+        writers.remove(&path);
+    }
+    let wtr = match writers.entry(path.clone()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(entry.key())?;
+            let is_empty = file.metadata()?.len() == 0;
+            let mut wtr = Writer::from_writer(file);
+            if is_empty {
+                wtr.write_record(header)?;
+            }
+            entry.insert(wtr)
+        }
+    };
+
+    let result = write_rows(wtr).and_then(|()| Ok(wtr.flush()?));
+    if result.is_err() {
+        // Drop any partially buffered row, along with the writer, which is
+        // reopened by the next write.
+        writers.remove(&path);
+    }
+    result.map_err(Into::into)
+}
+
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+fn log_fft_stats(stat_collector: FFTLoggingInfo) -> Result<(), Box<dyn Error + Send + Sync>> {
+    write_profiling_csv(
+        "cpu_fft_times.csv",
+        &["size", "log_n", "fft_type", "total_duration (ms)", "label"],
+        |wtr| {
+            wtr.write_record(&[
+                stat_collector.size.to_string(),
+                stat_collector.logn.to_string(),
+                stat_collector.fft_type,
+                stat_collector.fft_duration.to_string(),
+                stat_collector.label.unwrap_or_default(),
+            ])
+        },
+    )
 }
 
-fn log_fft_stats(stat_collector:FFTLoggingInfo)-> Result<(), Box<dyn Error>>
-{  
-    let filename = "cpu_fft_times.csv";
-    let file_exists = Path::new(filename).exists();
-    // Open the file in append mode, create it if it does not exist
-    let file = std::fs::OpenOptions::new()
-        .write(true)
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+fn log_msm_stats(stat_collector: MSMLoggingInfo) -> Result<(), Box<dyn Error + Send + Sync>> {
+    write_profiling_csv(
+        "cpu_msm_times.csv",
+        &["num_coeffs", "msm_duration", "label"],
+        |wtr| {
+            wtr.write_record(&[
+                &stat_collector.num_coeffs,
+                &stat_collector.msm_duration,
+                &stat_collector.label.unwrap_or_default(),
+            ])
+        },
+    )
+}
+
+#[cfg(feature = "profiling-json")]
+fn log_fft_stats(stat_collector: FFTLoggingInfo) -> Result<(), Box<dyn Error + Send + Sync>> {
+    log_json_line("cpu_fft_times.jsonl", &stat_collector)
+}
+
+#[cfg(feature = "profiling-json")]
+fn log_msm_stats(stat_collector: MSMLoggingInfo) -> Result<(), Box<dyn Error + Send + Sync>> {
+    log_json_line("cpu_msm_times.jsonl", &stat_collector)
+}
+
+/// Appends `record` as a single line of JSON to the profiling file `filename`.
+#[cfg(feature = "profiling-json")]
+fn log_json_line<T: Serialize>(
+    filename: &str,
+    record: &T,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(filename)?;
+        .open(profiling_file(filename))?;
+
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// How profiled FFTs and MSMs are recorded, as set by [`set_profiling_mode`].
+#[cfg(feature = "profiling")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfilingMode {
+    /// Each operation is written to the profiling files as its own row, as
+    /// soon as it completes. This is the default.
+    Rows,
+    /// Operations are aggregated in memory, per kind of operation, size and
+    /// device, and only written out by [`flush_profiling`].
+    Histogram,
+}
+
+/// Selects how profiled operations are recorded. [`ProfilingMode::Histogram`]
+/// keeps the profiling files small for callers performing many operations of
+/// the same sizes.
+#[cfg(feature = "profiling")]
+pub fn set_profiling_mode(mode: ProfilingMode) {
+    PROFILING_HISTOGRAM.store(mode == ProfilingMode::Histogram, Ordering::Relaxed);
+}
+
+/// The kind of operation ("fft" or "msm"), size and device of the operations
+/// aggregated into one [`OpSummary`].
+#[cfg(feature = "profiling")]
+type SummaryKey = (&'static str, usize, Device);
+
+/// The aggregated timings of a set of operations.
+#[cfg(feature = "profiling")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct OpSummary {
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
 
-    // Create a CSV writer
-    let mut wtr = Writer::from_writer(file);
+#[cfg(feature = "profiling")]
+impl OpSummary {
+    fn new(duration: Duration) -> Self {
+        OpSummary {
+            count: 1,
+            total: duration,
+            min: duration,
+            max: duration,
+        }
+    }
 
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.min = std::cmp::min(self.min, duration);
+        self.max = std::cmp::max(self.max, duration);
+    }
+}
 
-    if !file_exists {
-        wtr.write_record(&["size", "log_n", "fft_type", "total_duration (ms)"])?;
+/// Records an operation in profiling, either as a row with `log_row` or, in
+/// [`ProfilingMode::Histogram`], into the aggregated timings.
+#[cfg(feature = "profiling")]
+fn record_profiled_op(key: SummaryKey, duration: Duration, log_row: impl FnOnce()) {
+    if !PROFILING_HISTOGRAM.load(Ordering::Relaxed) {
+        return log_row();
     }
-    // Write the record with proper type conversion
-    wtr.write_record(&[
-        stat_collector.size.to_string(),
-        stat_collector.logn.to_string(),
-        stat_collector.fft_type,
-        stat_collector.fft_duration.to_string(),
-    ])?;
-    wtr.flush()?;
-    Ok(())
- 
+
+    PROFILING_SUMMARIES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .entry(key)
+        .and_modify(|summary| summary.record(duration))
+        .or_insert_with(|| OpSummary::new(duration));
 }
 
-fn log_msm_stats(stat_collector:MSMLoggingInfo)-> Result<(), Box<dyn Error>>
-{   
-    let filename = "cpu_msm_times.csv";
-    let file_exists = Path::new(filename).exists();
-    // Open or create the file
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(filename)?;
-    // Create a CSV writer
-      
-    let mut wtr = csv::Writer::from_writer(file);
-
-      // Write header if the file does not already exist
-      if !file_exists {
-          wtr.write_record(&["num_coeffs", "msm_duration"])?;
-      }
-    
-    // Write the logging information
-    wtr.write_record(&[
-        &stat_collector.num_coeffs,
-        &stat_collector.msm_duration,
-    ])?;
-    // Ensure all data is written to the file
-    wtr.flush()?;
+#[cfg(feature = "profiling")]
+#[derive(Serialize, Debug)]
+struct SummaryLoggingInfo {
+    op: &'static str,
+    size: usize,
+    device: String,
+    count: u64,
+    total_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+}
+
+/// Writes the timings aggregated in [`ProfilingMode::Histogram`] to the
+/// profiling directory, one row per kind of operation, size and device, and
+/// clears them. The rows are appended to `profiling_summary.csv`, or to
+/// `profiling_summary.jsonl` with the `profiling-json` feature. Errors are
+/// passed to the handler set with [`set_profiling_error_handler`].
+///
+/// Aggregated timings that are never flushed are lost when the process exits;
+/// a [`ProfilingFlushGuard`] held in `main` flushes them when dropped.
+#[cfg(feature = "profiling")]
+pub fn flush_profiling() {
+    let summaries = PROFILING_SUMMARIES.lock().unwrap().take();
+    let mut rows: Vec<_> = summaries
+        .unwrap_or_default()
+        .into_iter()
+        .map(|((op, size, device), summary)| SummaryLoggingInfo {
+            op,
+            size,
+            device: format!("{:?}", device).to_lowercase(),
+            count: summary.count,
+            total_ms: summary.total.as_secs_f64() * 1000.0,
+            min_ms: summary.min.as_secs_f64() * 1000.0,
+            max_ms: summary.max.as_secs_f64() * 1000.0,
+        })
+        .collect();
+    if rows.is_empty() {
+        return;
+    }
+    rows.sort_by(|a, b| (a.op, a.size, &a.device).cmp(&(b.op, b.size, &b.device)));
+
+    if let Err(e) = log_summaries(&rows) {
+        report_profiling_error(ProfilingError::Summary(e));
+    }
+}
+
+/// Calls [`flush_profiling`] when dropped.
+#[cfg(feature = "profiling")]
+#[derive(Debug, Default)]
+#[must_use]
+pub struct ProfilingFlushGuard;
+
+#[cfg(feature = "profiling")]
+impl Drop for ProfilingFlushGuard {
+    fn drop(&mut self) {
+        flush_profiling();
+    }
+}
+
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+fn log_summaries(rows: &[SummaryLoggingInfo]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let header = [
+        "op",
+        "size",
+        "device",
+        "count",
+        "total_duration (ms)",
+        "min_duration (ms)",
+        "max_duration (ms)",
+    ];
+    write_profiling_csv("profiling_summary.csv", &header, |wtr| {
+        for row in rows {
+            wtr.write_record(&[
+                row.op.to_string(),
+                row.size.to_string(),
+                row.device.clone(),
+                row.count.to_string(),
+                row.total_ms.to_string(),
+                row.min_ms.to_string(),
+                row.max_ms.to_string(),
+            ])?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(feature = "profiling-json")]
+fn log_summaries(rows: &[SummaryLoggingInfo]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for row in rows {
+        log_json_line("profiling_summary.jsonl", row)?;
+    }
     Ok(())
 }
 
+/// The device on which an FFT or MSM was performed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Device {
+    /// The operation ran on the CPU.
+    Cpu,
+    /// The operation ran on the GPU.
+    Gpu,
+}
+
+/// Timing information for a single FFT or MSM, as returned by [`fft_timed`]
+/// and [`multiexp_timed`].
+#[derive(Clone, Copy, Debug)]
+pub struct OpStats {
+    /// Wall-clock time spent performing the operation.
+    pub duration: Duration,
+    /// The device the operation ran on.
+    pub device: Device,
+    /// The number of elements in the FFT, or the number of terms in the MSM.
+    pub size: usize,
+}
+
 /// This represents an element of a group with basic operations that can be
 /// performed. This allows an FFT implementation (for example) to operate
 /// generically over either a field or elliptic curve group.
-pub trait FftGroup<Scalar: Field>:
-    Copy + Send + Sync + 'static + GroupOpsOwned + ScalarMulOwned<Scalar>
-{
-}
+pub trait FftGroup<Scalar: Field>: FftGroupLocal<Scalar> + 'static {}
 
 impl<T, Scalar> FftGroup<Scalar> for T
 where
     Scalar: Field,
-    T: Copy + Send + Sync + 'static + GroupOpsOwned + ScalarMulOwned<Scalar>,
+    T: FftGroupLocal<Scalar> + 'static,
+{
+}
+
+/// An [`FftGroup`] that may borrow data, i.e. without the `'static` bound.
+/// This is all that the CPU FFT routines require, so they can also operate on
+/// types with a non-`'static` lifetime.
+pub trait FftGroupLocal<Scalar: Field>:
+    Copy + Send + Sync + GroupOpsOwned + ScalarMulOwned<Scalar>
 {
 }
 
-fn multiexp_serial<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C], acc: &mut C::Curve) {
-    let coeffs: Vec<_> = coeffs.iter().map(|a| a.to_repr()).collect();
+impl<T, Scalar> FftGroupLocal<Scalar> for T
+where
+    Scalar: Field,
+    T: Copy + Send + Sync + GroupOpsOwned + ScalarMulOwned<Scalar>,
+{
+}
 
-    let c = if bases.len() < 4 {
+/// Heuristic choice of the Pippenger window size for `len` bases.
+fn multiexp_window_size(len: usize) -> usize {
+    if len < 4 {
         1
-    } else if bases.len() < 32 {
+    } else if len < 32 {
         3
     } else {
-        (f64::from(bases.len() as u32)).ln().ceil() as usize
-    };
+        (f64::from(len as u32)).ln().ceil() as usize
+    }
+}
 
-    fn get_at<F: PrimeField>(segment: usize, c: usize, bytes: &F::Repr) -> usize {
-        let skip_bits = segment * c;
-        let skip_bytes = skip_bits / 8;
+/// The threshold set with [`set_msm_parallel_threshold`], or zero if unset.
+static MSM_PARALLEL_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
 
-        if skip_bytes >= (F::NUM_BITS as usize + 7) / 8 {
-            return 0;
-        }
+/// Sets the number of terms above which CPU multi-exponentiations are split
+/// across the thread pool, rather than computed with a single serial
+/// Pippenger pass on the current thread. Below a few hundred terms, spawning
+/// the tasks can cost more than it saves.
+///
+/// By default, or when set to zero, this is the number of threads. Since each
+/// thread needs at least one term, a threshold below the number of threads
+/// has the same effect as the default.
+pub fn set_msm_parallel_threshold(threshold: usize) {
+    MSM_PARALLEL_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
 
-        let mut v = [0; 8];
-        for (v, o) in v.iter_mut().zip(bytes.as_ref()[skip_bytes..].iter()) {
-            *v = *o;
+/// Returns whether a CPU multi-exponentiation of `len` terms is split across
+/// the thread pool; see [`set_msm_parallel_threshold`].
+fn msm_is_parallel(len: usize) -> bool {
+    let threshold = MSM_PARALLEL_THRESHOLD.load(Ordering::Relaxed);
+    len > std::cmp::max(threshold, multicore::current_num_threads())
+}
+
+/// Heuristic choice of the Pippenger window size for a multi-exponentiation
+/// of `len` bases that is split across the thread pool.
+fn parallel_multiexp_window_size(len: usize) -> usize {
+    if msm_is_parallel(len) {
+        multiexp_window_size(len / multicore::current_num_threads())
+    } else {
+        multiexp_window_size(len)
+    }
+}
+
+/// Returns whether `F::Repr` stores its bytes in big-endian order.
+///
+/// The multi-exponentiation window readers, such as [`get_at`], read scalar
+/// representations as little-endian. Every field we use has little-endian
+/// representations, but some external curve implementations do not, so the
+/// byte order is detected from the representation of one.
+fn repr_is_big_endian<F: PrimeField>() -> bool {
+    let one = F::ONE.to_repr();
+    let bytes = one.as_ref();
+    let big_endian = bytes[0] != 1;
+    debug_assert_eq!(
+        bytes[if big_endian { bytes.len() - 1 } else { 0 }],
+        1,
+        "scalar representation of one is not 1 in either byte order"
+    );
+    big_endian
+}
+
+/// Returns the little-endian byte representation of `scalar`, reversing the
+/// bytes of `to_repr` if `big_endian` is set. `big_endian` must be the result
+/// of [`repr_is_big_endian`] for `F`.
+fn scalar_to_repr_le<F: PrimeField>(scalar: &F, big_endian: bool) -> F::Repr {
+    let mut repr = scalar.to_repr();
+    if big_endian {
+        repr.as_mut().reverse();
+    }
+    repr
+}
+
+/// Converts scalars into their little-endian byte representations, as
+/// consumed by [`cpu_multiexp_with_reprs`].
+fn scalars_to_reprs<F: PrimeField>(scalars: &[F]) -> Vec<F::Repr> {
+    let big_endian = repr_is_big_endian::<F>();
+    let mut reprs = vec![F::Repr::default(); scalars.len()];
+    parallelize(&mut reprs, |reprs, start| {
+        for (repr, scalar) in reprs.iter_mut().zip(scalars[start..].iter()) {
+            *repr = scalar_to_repr_le(scalar, big_endian);
         }
+    });
+    reprs
+}
+
+/// Like [`scalars_to_reprs`], but on the current thread only.
+fn scalars_to_reprs_serial<F: PrimeField>(scalars: &[F]) -> Vec<F::Repr> {
+    let big_endian = repr_is_big_endian::<F>();
+    scalars
+        .iter()
+        .map(|scalar| scalar_to_repr_le(scalar, big_endian))
+        .collect()
+}
+
+/// Returns the `segment`-th window of `c` bits of the little-endian scalar
+/// representation `bytes`.
+///
+/// `bytes` must be little-endian even if `F::Repr` is not; scalars should be
+/// converted with [`scalars_to_reprs`] rather than `to_repr`.
+fn get_at<F: PrimeField>(segment: usize, c: usize, bytes: &F::Repr) -> usize {
+    let skip_bits = segment * c;
+    let skip_bytes = skip_bits / 8;
+
+    if skip_bytes >= (F::NUM_BITS as usize + 7) / 8 {
+        return 0;
+    }
+
+    let mut v = [0; 8];
+    for (v, o) in v.iter_mut().zip(bytes.as_ref()[skip_bytes..].iter()) {
+        *v = *o;
+    }
+
+    let mut tmp = u64::from_le_bytes(v);
+    tmp >>= skip_bits - (skip_bytes * 8);
+    tmp %= 1 << c;
 
-        let mut tmp = u64::from_le_bytes(v);
-        tmp >>= skip_bits - (skip_bytes * 8);
-        tmp %= 1 << c;
+    tmp as usize
+}
+
+/// The widest window, in bits, that [`window_digits`] can store, and so the
+/// widest window of [`multiexp_serial`]. Wider windows would need more than
+/// $2^{32}$ buckets anyway.
+const MAX_DIGIT_BITS: usize = 32;
+
+/// Returns the `c`-bit windows of every scalar representation in `coeffs`, as
+/// read by [`get_at`], for each of the `C::Scalar::NUM_BITS / c + 1` segments
+/// of [`multiexp_serial`]. The windows are stored segment by segment, so that
+/// those of `segment` are `digits[segment * n..(segment + 1) * n]` for $n$
+/// scalars.
+///
+/// Each representation is read once, in a single pass over its bytes, rather
+/// than once per segment. This takes 4 bytes per scalar and segment.
+///
+/// This function will panic if `c` is not between 1 and [`MAX_DIGIT_BITS`].
+fn window_digits<F: PrimeField>(coeffs: &[F::Repr], c: usize) -> Vec<u32> {
+    assert!(
+        (1..=MAX_DIGIT_BITS).contains(&c),
+        "window size {} is out of range",
+        c
+    );
 
-        tmp as usize
+    let n = coeffs.len();
+    let segments = (F::NUM_BITS as usize / c) + 1;
+    let mask = (1u64 << c) - 1;
+    let mut digits = vec![0; n * segments];
+    for (i, coeff) in coeffs.iter().enumerate() {
+        let mut bytes = coeff.as_ref().iter();
+        // At most c + 7 bits are buffered, which fits in a u64 for c <= 32.
+        let (mut buffer, mut buffered) = (0u64, 0);
+        for segment in 0..segments {
+            while buffered < c {
+                match bytes.next() {
+                    Some(byte) => buffer |= u64::from(*byte) << buffered,
+                    None => break,
+                }
+                buffered += 8;
+            }
+            digits[segment * n + i] = (buffer & mask) as u32;
+            buffer >>= c;
+            buffered = buffered.saturating_sub(c);
+        }
     }
+    digits
+}
 
+fn multiexp_serial<C: CurveAffine>(
+    coeffs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+    acc: &mut C::Curve,
+    c: usize,
+) {
     let segments = (C::Scalar::NUM_BITS as usize / c) + 1;
+    let digits = window_digits::<C::Scalar>(coeffs, c);
+    let n = coeffs.len();
 
     for current_segment in (0..segments).rev() {
-        for _ in 0..c {
-            *acc = acc.double();
+        let digits = &digits[current_segment * n..(current_segment + 1) * n];
+        multiexp_serial_segment(digits, bases, acc, c);
+    }
+}
+
+/// Like [`multiexp_serial`], but with the window size fixed at compile time,
+/// so that the number of segments and of buckets are constants of each
+/// monomorphized copy. Stable Rust cannot size an array from `C_WINDOW`, so
+/// the buckets are still allocated, but with a constant length.
+fn multiexp_serial_const<C: CurveAffine, const C_WINDOW: usize>(
+    coeffs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+    acc: &mut C::Curve,
+) {
+    assert!(
+        (1..=MAX_MSM_WINDOW_BITS).contains(&C_WINDOW),
+        "window size {} is out of range",
+        C_WINDOW
+    );
+    let segments = (C::Scalar::NUM_BITS as usize / C_WINDOW) + 1;
+    let digits = window_digits::<C::Scalar>(coeffs, C_WINDOW);
+    let n = coeffs.len();
+
+    for current_segment in (0..segments).rev() {
+        let digits = &digits[current_segment * n..(current_segment + 1) * n];
+        multiexp_serial_segment(digits, bases, acc, C_WINDOW);
+    }
+}
+
+/// Performs one iteration of the outer loop of [`multiexp_serial`]: shifts
+/// `acc` by `c` bits, then adds the contribution of each base times its
+/// `c`-bit window `digits` at the current segment, from [`window_digits`].
+#[inline]
+fn multiexp_serial_segment<C: CurveAffine>(
+    digits: &[u32],
+    bases: &[C],
+    acc: &mut C::Curve,
+    c: usize,
+) {
+    for _ in 0..c {
+        *acc = acc.double();
+    }
+
+    // With `simd-msm`, the buckets are filled with batched affine additions
+    // instead, and are then summed by parts as below.
+    #[cfg(feature = "simd-msm")]
+    {
+        let mut running_sum = C::Curve::identity();
+        for bucket in batch_affine::bucket_sums(digits, bases, c).iter().rev() {
+            running_sum += bucket;
+            *acc += &running_sum;
         }
+    }
 
+    #[cfg(not(feature = "simd-msm"))]
+    {
         #[derive(Clone, Copy)]
         enum Bucket<C: CurveAffine> {
             None,
@@ -187,12 +795,29 @@ fn multiexp_serial<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C], acc: &mut
             }
         }
 
+        // Otherwise `1 << c` would overflow, and the bucket count with it.
+        assert!(
+            c < usize::BITS as usize,
+            "window size {} is out of range",
+            c
+        );
         let mut buckets: Vec<Bucket<C>> = vec![Bucket::None; (1 << c) - 1];
 
-        for (coeff, base) in coeffs.iter().zip(bases.iter()) {
-            let coeff = get_at::<C::Scalar>(current_segment, c, coeff);
+        for (&digit, base) in digits.iter().zip(bases.iter()) {
+            let coeff = digit as usize;
             if coeff != 0 {
+                #[cfg(not(feature = "fast-msm"))]
                 buckets[coeff - 1].add_assign(base);
+
+                // SAFETY: `window_digits` masks each window to `c` bits, so
+                // `0 < coeff < 1 << c`, and `coeff - 1 < (1 << c) - 1`, which is
+                // `buckets.len()` since `1 << c` does not overflow, as asserted
+                // above.
+                #[cfg(feature = "fast-msm")]
+                #[allow(unsafe_code)]
+                unsafe {
+                    buckets.get_unchecked_mut(coeff - 1).add_assign(base);
+                }
             }
         }
 
@@ -208,10 +833,143 @@ fn multiexp_serial<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C], acc: &mut
     }
 }
 
+/// Like [`multiexp_serial`], but with the buckets stored as a structure of
+/// arrays: a single `Vec` of projective points, reused across segments, and a
+/// bitset marking which of them are occupied. Empty buckets are skipped
+/// during the summation, and no per-bucket tag is stored.
+fn multiexp_serial_soa<C: CurveAffine>(
+    coeffs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+    acc: &mut C::Curve,
+    c: usize,
+) {
+    let segments = (C::Scalar::NUM_BITS as usize / c) + 1;
+    let num_buckets = (1 << c) - 1;
+
+    let mut buckets = vec![C::Curve::identity(); num_buckets];
+    let mut occupied = vec![0u64; (num_buckets + 63) / 64];
+    let is_occupied = |occupied: &[u64], i: usize| (occupied[i / 64] >> (i % 64)) & 1 == 1;
+
+    for current_segment in (0..segments).rev() {
+        for _ in 0..c {
+            *acc = acc.double();
+        }
+
+        for word in occupied.iter_mut() {
+            *word = 0;
+        }
+
+        for (coeff, base) in coeffs.iter().zip(bases.iter()) {
+            let coeff = get_at::<C::Scalar>(current_segment, c, coeff);
+            if coeff != 0 {
+                let i = coeff - 1;
+                if is_occupied(&occupied, i) {
+                    buckets[i] += *base;
+                } else {
+                    buckets[i] = base.to_curve();
+                    occupied[i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+
+        // Summation by parts, as in `multiexp_serial`
+        let mut running_sum = C::Curve::identity();
+        for (i, bucket) in buckets.iter().enumerate().rev() {
+            if is_occupied(&occupied, i) {
+                running_sum += bucket;
+            }
+            *acc += &running_sum;
+        }
+    }
+}
+
+/// Performs a multi-exponentiation operation on the current thread only, like
+/// [`multiexp_serial_pub`], using structure-of-arrays bucket storage.
+///
+/// This is a prototype of an alternative bucket layout with better locality
+/// for large window sizes; it returns the same result as
+/// [`multiexp_serial_pub`], and the two are compared in the `msm` benchmark.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn multiexp_serial_soa_pub<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let coeffs = scalars_to_reprs_serial(coeffs);
+    let mut acc = C::Curve::identity();
+    multiexp_serial_soa(&coeffs, bases, &mut acc, multiexp_window_size(bases.len()));
+    acc
+}
+
+/// Performs a multi-exponentiation operation on the current thread only.
+///
+/// This function will panic if coeffs and bases have a different length.
+///
+/// Unlike [`best_multiexp_cpu`], this never touches the thread pool and never
+/// logs profiling data, so its result and behaviour do not depend on the
+/// number of available threads.
+pub fn multiexp_serial_pub<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let coeffs = scalars_to_reprs_serial(coeffs);
+    let mut acc = C::Curve::identity();
+    multiexp_serial(&coeffs, bases, &mut acc, multiexp_window_size(bases.len()));
+    acc
+}
+
+/// Performs a multi-exponentiation operation on the current thread only, like
+/// [`multiexp_serial_pub`], but with a window of `C_WINDOW` bits fixed at
+/// compile time rather than chosen from the number of bases.
+///
+/// This function will panic if coeffs and bases have a different length, or
+/// if `C_WINDOW` is zero or wider than [`MAX_MSM_WINDOW_BITS`].
+pub fn multiexp_serial_const_pub<C: CurveAffine, const C_WINDOW: usize>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let coeffs = scalars_to_reprs_serial(coeffs);
+    let mut acc = C::Curve::identity();
+    multiexp_serial_const::<C, C_WINDOW>(&coeffs, bases, &mut acc);
+    acc
+}
+
+/// Performs the same multi-exponentiation as [`multiexp_serial_pub`], and also
+/// returns the value of the accumulator after each segment of the window
+/// decomposition, from the most significant window to the least significant.
+/// The last entry is the result.
+///
+/// Comparing these traces between two implementations shows which window of
+/// scalar bits first introduces a divergence.
+///
+/// This function will panic if coeffs and bases have a different length.
+#[cfg(feature = "debug-tracing")]
+pub fn multiexp_serial_trace<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+) -> (C::Curve, Vec<C::Curve>) {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let coeffs = scalars_to_reprs_serial(coeffs);
+    let c = multiexp_window_size(bases.len());
+    let segments = (C::Scalar::NUM_BITS as usize / c) + 1;
+    let digits = window_digits::<C::Scalar>(&coeffs, c);
+    let n = coeffs.len();
+
+    let mut acc = C::Curve::identity();
+    let mut trace = Vec::with_capacity(segments);
+    for current_segment in (0..segments).rev() {
+        let digits = &digits[current_segment * n..(current_segment + 1) * n];
+        multiexp_serial_segment(digits, bases, &mut acc, c);
+        trace.push(acc);
+    }
+    (acc, trace)
+}
+
 /// Performs a small multi-exponentiation operation.
 /// Uses the double-and-add algorithm with doublings shared across points.
 pub fn small_multiexp<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
-    let coeffs: Vec<_> = coeffs.iter().map(|a| a.to_repr()).collect();
+    let coeffs = scalars_to_reprs_serial(coeffs);
     let mut acc = C::Curve::identity();
 
     // for byte idx
@@ -232,31 +990,608 @@ pub fn small_multiexp<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::C
     acc
 }
 
-#[cfg(feature = "icicle_gpu")]
-/// Performs a multi-exponentiation operation on GPU using Icicle library
-pub fn best_multiexp_gpu<C: CurveAffine>(coeffs: &[C::Scalar], is_lagrange: bool) -> C::Curve {
-    let scalars_ptr: DeviceBuffer<::icicle::curves::bn254::ScalarField_BN254> =
-        icicle::copy_scalars_to_device::<C>(coeffs);
+/// Computes $aG + bH$ with Straus's (or Shamir's) trick: a single
+/// double-and-add pass over the bits of both scalars, which adds one of the
+/// precomputed points $G$, $H$ or $G + H$ for each pair of bits, so that the
+/// doublings are shared between the two terms.
+pub fn multiexp2<C: CurveAffine>(a: C::Scalar, g: C, b: C::Scalar, h: C) -> C::Curve {
+    let reprs = scalars_to_reprs_serial(&[a, b]);
+    let (a, b) = (reprs[0].as_ref(), reprs[1].as_ref());
+    let g_plus_h = (g + h).to_affine();
+    let bit = |bytes: &[u8], pos: usize| (bytes[pos / 8] >> (pos % 8)) & 1 == 1;
 
-    return icicle::multiexp_on_device::<C>(scalars_ptr, is_lagrange);
+    let mut acc = C::Curve::identity();
+    for pos in (0..C::Scalar::NUM_BITS as usize).rev() {
+        acc = acc.double();
+        match (bit(a, pos), bit(b, pos)) {
+            (true, false) => acc += g,
+            (false, true) => acc += h,
+            (true, true) => acc += g_plus_h,
+            (false, false) => {}
+        }
+    }
+
+    acc
 }
 
-/// Performs a multi-exponentiation operation.
+/// The window width of the non-adjacent form used by [`small_multiexp_wnaf`].
+const SMALL_MULTIEXP_WNAF_WINDOW: usize = 3;
+
+/// Performs a small multi-exponentiation operation like [`small_multiexp`],
+/// returning the same result.
 ///
-/// This function will panic if coeffs and bases have a different length.
+/// The scalars are recoded into width-3 windowed non-adjacent form, whose
+/// nonzero digits are odd, lie in $\{-3, -1, 1, 3\}$ and are separated by at
+/// least two zeros, so that fewer additions are performed than with plain
+/// double-and-add. Doublings are still shared across points.
 ///
-/// This will use multithreading if beneficial.
-pub fn best_multiexp_cpu<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+/// This function will panic if coeffs and bases have a different length.
+pub fn small_multiexp_wnaf<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    fn wnaf_form<F: PrimeField>(bytes: &F::Repr) -> Vec<i8> {
+        let bytes = bytes.as_ref();
+        let bit = |pos: usize| -> u64 {
+            bytes
+                .get(pos / 8)
+                .map_or(0, |byte| u64::from((byte >> (pos % 8)) & 1))
+        };
+
+        let width = 1u64 << SMALL_MULTIEXP_WNAF_WINDOW;
+        let num_bits = bytes.len() * 8;
+        let mut wnaf = Vec::with_capacity(num_bits + 1);
+        let mut carry = 0;
+        let mut pos = 0;
+        while pos < num_bits || carry != 0 {
+            let window_val = carry
+                + (0..SMALL_MULTIEXP_WNAF_WINDOW)
+                    .map(|i| bit(pos + i) << i)
+                    .sum::<u64>();
+            if window_val & 1 == 0 {
+                wnaf.push(0);
+                pos += 1;
+            } else {
+                wnaf.push(if window_val < width / 2 {
+                    carry = 0;
+                    window_val as i8
+                } else {
+                    carry = 1;
+                    window_val as i8 - width as i8
+                });
+                wnaf.extend(std::iter::repeat(0).take(SMALL_MULTIEXP_WNAF_WINDOW - 1));
+                pos += SMALL_MULTIEXP_WNAF_WINDOW;
+            }
+        }
+        wnaf
+    }
+
+    assert_eq!(coeffs.len(), bases.len());
+
+    let wnafs: Vec<_> = scalars_to_reprs_serial(coeffs)
+        .iter()
+        .map(wnaf_form::<C::Scalar>)
+        .collect();
+    // The odd multiples P, 3P of each base
+    let tables: Vec<[C::Curve; 2]> = bases
+        .iter()
+        .map(|base| {
+            let base = base.to_curve();
+            [base, base.double() + &base]
+        })
+        .collect();
+
+    let len = wnafs.iter().map(|wnaf| wnaf.len()).max().unwrap_or(0);
+    let mut acc = C::Curve::identity();
+    for i in (0..len).rev() {
+        acc = acc.double();
+        for (wnaf, table) in wnafs.iter().zip(tables.iter()) {
+            match wnaf.get(i).copied().unwrap_or(0) {
+                0 => {}
+                digit if digit > 0 => acc += &table[(digit / 2) as usize],
+                digit => acc -= &table[(-digit / 2) as usize],
+            }
+        }
+    }
+
+    acc
+}
+
+/// A curve that the GPU multi-exponentiation backend supports.
+///
+/// The Icicle kernels are only built for the BN254 `G1` group, which is the
+/// only implementor. This trait is sealed, so that the GPU entry points reject
+/// any other curve at compile time.
+#[cfg(feature = "icicle_gpu")]
+pub trait GpuCurve: CurveAffine + sealed::GpuCurve {}
+
+#[cfg(feature = "icicle_gpu")]
+impl GpuCurve for halo2curves::bn256::G1Affine {}
+
+#[cfg(feature = "icicle_gpu")]
+mod sealed {
+    /// Sealed trait to keep [`GpuCurve`](super::GpuCurve) from being
+    /// implemented outside this crate.
+    pub trait GpuCurve {}
+
+    impl GpuCurve for halo2curves::bn256::G1Affine {}
+}
+
+/// Returns whether the GPU multi-exponentiation backend can handle curve `C`,
+/// for code that is generic over the curve and picks a backend at runtime.
+///
+/// This checks that both the scalar and base fields of `C` match those of
+/// BN254, the curve of [`GpuCurve`].
+#[cfg(feature = "icicle_gpu")]
+pub(crate) fn gpu_supports_curve<C: CurveAffine>() -> bool {
+    use halo2curves::bn256;
+
+    C::Scalar::MODULUS == bn256::Fr::MODULUS && C::Base::MODULUS == bn256::Fq::MODULUS
+}
+
+/// Selects which GPU runs the GPU multi-exponentiations, by device index. This
+/// must be called before the GPU is initialized, e.g. by [`warm_up_gpu`]. If
+/// the index does not exist, a warning is logged when the GPU is initialized
+/// and multi-exponentiations fall back to the CPU.
+#[cfg(feature = "icicle_gpu")]
+pub fn set_gpu_device(index: usize) {
+    icicle::set_gpu_device(index)
+}
+
+/// Returns the index of the GPU that runs the GPU multi-exponentiations.
+#[cfg(feature = "icicle_gpu")]
+pub fn get_gpu_device() -> usize {
+    icicle::get_gpu_device()
+}
+
+/// Initializes the GPU ahead of the first multi-exponentiation, so that it is
+/// not penalized by the setup cost. Returns whether a GPU is available; if it
+/// is not, GPU multi-exponentiations fall back to the CPU.
+#[cfg(feature = "icicle_gpu")]
+pub fn warm_up_gpu() -> bool {
+    icicle::warm_up_gpu()
+}
+
+/// Performs a multi-exponentiation operation on GPU using Icicle library
+#[cfg(feature = "icicle_gpu")]
+pub fn best_multiexp_gpu<C: GpuCurve>(coeffs: &[C::Scalar], is_lagrange: bool) -> C::Curve {
+    let scalars_ptr: DeviceBuffer<::icicle::curves::bn254::ScalarField_BN254> =
+        icicle::copy_scalars_to_device::<C>(coeffs);
+
+    return icicle::multiexp_on_device::<C>(scalars_ptr, is_lagrange);
+}
+
+/// Performs a multi-exponentiation operation on GPU using Icicle library, and
+/// returns how long it took alongside the result.
+#[cfg(feature = "icicle_gpu")]
+pub fn multiexp_gpu_timed<C: GpuCurve>(
+    coeffs: &[C::Scalar],
+    is_lagrange: bool,
+) -> (C::Curve, OpStats) {
+    let start_time = Instant::now();
+    let result = best_multiexp_gpu::<C>(coeffs, is_lagrange);
+    let stats = OpStats {
+        duration: start_time.elapsed(),
+        device: Device::Gpu,
+        size: coeffs.len(),
+    };
+
+    (result, stats)
+}
+
+/// Performs a multi-exponentiation operation on GPU using Icicle library,
+/// falling back to [`best_multiexp_cpu`] over `bases` if the GPU reports an
+/// error or does not support `C`.
+#[cfg(feature = "icicle_gpu")]
+pub fn best_multiexp_gpu_or_cpu<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    is_lagrange: bool,
+) -> C::Curve {
+    multiexp_with_fallback(coeffs, bases, || {
+        if !gpu_supports_curve::<C>() {
+            return Err(icicle::GpuError::UnsupportedCurve);
+        }
+        let scalars = icicle::try_copy_scalars_to_device::<C>(coeffs)?;
+        icicle::try_multiexp_on_device::<C>(scalars, is_lagrange)
+    })
+}
+
+/// A failure or inconsistency found by [`self_check_gpu`].
+#[cfg(feature = "icicle_gpu")]
+#[derive(Debug)]
+pub enum SelfCheckError {
+    /// No GPU could be initialized.
+    Unavailable,
+    /// The GPU reported an error.
+    Gpu(String),
+    /// The GPU and CPU multi-exponentiations over the first `size` test inputs
+    /// disagree.
+    MsmMismatch {
+        /// The number of inputs of the first diverging multi-exponentiation.
+        size: usize,
+    },
+}
+
+#[cfg(feature = "icicle_gpu")]
+impl std::fmt::Display for SelfCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelfCheckError::Unavailable => write!(f, "no GPU is available"),
+            SelfCheckError::Gpu(e) => write!(f, "GPU error: {}", e),
+            SelfCheckError::MsmMismatch { size } => {
+                write!(f, "GPU and CPU multiexps of size {} disagree", size)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "icicle_gpu")]
+impl std::error::Error for SelfCheckError {}
+
+/// Checks that GPU multi-exponentiations agree with [`best_multiexp_cpu`], e.g.
+/// at startup, to detect a faulty GPU or driver before relying on it.
+///
+/// Multi-exponentiations are computed over the first $1, 2, 4, \dots, 2^k$ of
+/// a fixed set of pseudorandom scalars and bases when provided `log_n` = $k$,
+/// and the first size at which the GPU and the CPU disagree is reported. The
+/// bases are copied to the device for this, leaving those used by
+/// [`best_multiexp_gpu`] untouched. There is no GPU FFT to check.
+#[cfg(feature = "icicle_gpu")]
+pub fn self_check_gpu<C: GpuCurve>(log_n: u32) -> Result<(), SelfCheckError> {
+    if !warm_up_gpu() {
+        return Err(SelfCheckError::Unavailable);
+    }
+
+    // A fixed seed lets a failure be reproduced.
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let n = 1 << log_n;
+    let scalars: Vec<C::Scalar> = (0..n).map(|_| C::Scalar::random(&mut rng)).collect();
+    let projective: Vec<C::Curve> = (0..n).map(|_| C::Curve::random(&mut rng)).collect();
+    let mut bases = vec![C::identity(); n];
+    C::Curve::batch_normalize(&projective, &mut bases);
+
+    for size in (0..=log_n).map(|i| 1 << i) {
+        let (scalars, bases) = (&scalars[..size], &bases[..size]);
+        let gpu = icicle::try_multiexp_with_bases(scalars, bases)
+            .map_err(|e| SelfCheckError::Gpu(e.to_string()))?;
+        if gpu != best_multiexp_cpu(scalars, bases) {
+            return Err(SelfCheckError::MsmMismatch { size });
+        }
+    }
+    Ok(())
+}
+
+/// Runs the GPU multi-exponentiation `gpu`, and falls back to computing it with
+/// [`best_multiexp_cpu`] if that fails.
+#[cfg_attr(not(feature = "icicle_gpu"), allow(dead_code))]
+fn multiexp_with_fallback<C: CurveAffine, E: std::fmt::Display>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    gpu: impl FnOnce() -> Result<C::Curve, E>,
+) -> C::Curve {
+    match gpu() {
+        Ok(result) => result,
+        Err(e) => {
+            log::warn!("GPU multiexp failed, falling back to CPU: {}", e);
+            best_multiexp_cpu(coeffs, bases)
+        }
+    }
+}
+
+/// Performs a multi-exponentiation operation.
+///
+/// This function will panic if coeffs and bases have a different length.
+///
+/// This will use multithreading if beneficial.
+pub fn best_multiexp_cpu<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    multiexp_timed(coeffs, bases).0
+}
+
+/// Performs a multi-exponentiation operation on the GPU if the `icicle_gpu`
+/// feature is enabled and the GPU can run it, and with [`best_multiexp_cpu`]
+/// otherwise.
+///
+/// The GPU is used for curves it supports, when one is available, for more
+/// than $2^{ICICLE\_SMALL\_K}$ inputs, and if no base is the identity, which
+/// the GPU cannot represent. If the GPU reports an error, this falls back to
+/// [`best_multiexp_cpu`].
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn best_multiexp<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    #[cfg(feature = "icicle_gpu")]
+    if gpu_supports_curve::<C>()
+        && !icicle::should_use_cpu_msm(coeffs.len())
+        && warm_up_gpu()
+        && !bases.iter().any(|base| bool::from(base.is_identity()))
+    {
+        return multiexp_with_fallback(coeffs, bases, || {
+            icicle::try_multiexp_with_bases(coeffs, bases)
+        });
+    }
+
+    best_multiexp_cpu(coeffs, bases)
+}
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], after
+/// checking that every base is on the curve. This is meant for bases that may
+/// not have been validated when they were read, e.g. with
+/// `SerdeFormat::RawBytesUnchecked`, where invalid points would otherwise
+/// silently produce a meaningless result.
+///
+/// Returns the index of the first base that is not on the curve, if any.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn cpu_multiexp_validated<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+) -> Result<C::Curve, usize> {
+    let invalid = parallelize_map(bases, |bases, start| {
+        bases
+            .iter()
+            .position(|base| !bool::from(base.is_on_curve()))
+            .map(|i| start + i)
+    })
+    .into_iter()
+    .flatten()
+    .next();
+
+    match invalid {
+        Some(index) => Err(index),
+        None => Ok(best_multiexp_cpu(coeffs, bases)),
+    }
+}
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], and
+/// returns how long it took alongside the result.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn multiexp_timed<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> (C::Curve, OpStats) {
+    multiexp_timed_with_window(coeffs, bases, parallel_multiexp_window_size(coeffs.len()))
+}
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], over
+/// scalars that have already been converted with `PrimeField::to_repr`. This
+/// lets callers reuse the conversion across several MSMs over the same
+/// scalars.
+///
+/// If `C::Scalar` has big-endian representations, `reprs` are copied and
+/// byte-reversed first, which costs part of the saving.
+///
+/// This function will panic if reprs and bases have a different length.
+pub fn cpu_multiexp_with_reprs<C: CurveAffine>(
+    reprs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+) -> C::Curve {
+    assert_eq!(reprs.len(), bases.len());
+
+    if repr_is_big_endian::<C::Scalar>() {
+        let mut reprs = reprs.to_vec();
+        parallelize(&mut reprs, |reprs, _| {
+            for repr in reprs.iter_mut() {
+                repr.as_mut().reverse();
+            }
+        });
+        return cpu_multiexp_with_reprs_le(&reprs, bases);
+    }
+    cpu_multiexp_with_reprs_le(reprs, bases)
+}
+
+fn cpu_multiexp_with_reprs_le<C: CurveAffine>(
+    reprs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+) -> C::Curve {
+    let start_time = Instant::now();
+    let result = multiexp_reprs(reprs, bases, parallel_multiexp_window_size(reprs.len()));
+    record_msm_stats(reprs.len(), start_time);
+
+    result
+}
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], over
+/// bases given in their compressed encoding.
+///
+/// Each thread decompresses the bases of its own chunk with
+/// `GroupEncoding::from_bytes`, so no decompressed copy of all of `bases` is
+/// built up front. Returns an error if any of the bases is not a valid point
+/// encoding.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn cpu_multiexp_compressed<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C::Repr],
+) -> io::Result<C::Curve> {
+    fn decompress<C: CurveAffine>(bases: &[C::Repr]) -> io::Result<Vec<C>> {
+        bases
+            .iter()
+            .map(|base| {
+                Option::from(C::from_bytes(base)).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::Other, "invalid point encoding")
+                })
+            })
+            .collect()
+    }
+
+    fn multiexp_chunk<C: CurveAffine>(
+        coeffs: &[<C::Scalar as PrimeField>::Repr],
+        bases: &[C::Repr],
+        c: usize,
+    ) -> io::Result<C::Curve> {
+        let bases = decompress::<C>(bases)?;
+        let mut acc = C::Curve::identity();
+        multiexp_serial(coeffs, &bases, &mut acc, c);
+        Ok(acc)
+    }
+
+    assert_eq!(coeffs.len(), bases.len());
+
+    let start_time = Instant::now();
+    let coeffs = scalars_to_reprs(coeffs);
+    let c = parallel_multiexp_window_size(coeffs.len());
+
+    let num_threads = multicore::current_num_threads();
+    let result = if msm_is_parallel(coeffs.len()) {
+        let chunk = coeffs.len() / num_threads;
+        let mut results: Vec<io::Result<C::Curve>> = coeffs
+            .chunks(chunk)
+            .map(|_| Ok(C::Curve::identity()))
+            .collect();
+        multicore::scope(|scope| {
+            for ((coeffs, bases), acc) in coeffs
+                .chunks(chunk)
+                .zip(bases.chunks(chunk))
+                .zip(results.iter_mut())
+            {
+                scope.spawn(move |_| {
+                    *acc = multiexp_chunk::<C>(coeffs, bases, c);
+                });
+            }
+        });
+
+        let mut acc = C::Curve::identity();
+        for result in results {
+            acc += result?;
+        }
+        acc
+    } else {
+        multiexp_chunk::<C>(&coeffs, bases, c)?
+    };
+    record_msm_stats(coeffs.len(), start_time);
+
+    Ok(result)
+}
+
+/// The magic bytes at the start of serialized MSM inputs.
+const MSM_INPUTS_MAGIC: &[u8; 4] = b"MSMI";
+/// The version of the format written by [`serialize_msm_inputs`].
+const MSM_INPUTS_VERSION: u8 = 1;
+
+/// Writes the inputs of a multi-exponentiation to `writer`, so that they can
+/// be cached and read back with [`deserialize_msm_inputs`].
+///
+/// The format is a header of the magic bytes `MSMI`, a version byte and the
+/// number of terms as a little-endian `u64`, followed by each scalar as its
+/// `PrimeField::to_repr` and then each base in its compressed
+/// `GroupEncoding::to_bytes` encoding.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn serialize_msm_inputs<C: CurveAffine, W: io::Write>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    writer: &mut W,
+) -> io::Result<()> {
+    assert_eq!(coeffs.len(), bases.len());
+
+    writer.write_all(MSM_INPUTS_MAGIC)?;
+    writer.write_all(&[MSM_INPUTS_VERSION])?;
+    writer.write_all(&(coeffs.len() as u64).to_le_bytes())?;
+    for coeff in coeffs {
+        writer.write_all(coeff.to_repr().as_ref())?;
+    }
+    for base in bases {
+        writer.write_all(base.to_bytes().as_ref())?;
+    }
+    Ok(())
+}
+
+/// Reads multi-exponentiation inputs written by [`serialize_msm_inputs`],
+/// returning the scalars and the bases.
+///
+/// Returns an error if the header does not match, or if any of the scalars or
+/// bases is not a valid encoding.
+pub fn deserialize_msm_inputs<C: CurveAffine, R: io::Read>(
+    reader: &mut R,
+) -> io::Result<(Vec<C::Scalar>, Vec<C>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MSM_INPUTS_MAGIC {
+        return Err(invalid("not serialized MSM inputs"));
+    }
+    let mut version = [0u8; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != MSM_INPUTS_VERSION {
+        return Err(invalid("unsupported MSM inputs version"));
+    }
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| invalid("too many MSM inputs"))?;
+
+    // The length is untrusted, so the vectors grow as elements are read.
+    let mut coeffs = vec![];
+    for _ in 0..len {
+        let mut repr = <C::Scalar as PrimeField>::Repr::default();
+        reader.read_exact(repr.as_mut())?;
+        coeffs.push(
+            Option::from(C::Scalar::from_repr(repr))
+                .ok_or_else(|| invalid("invalid scalar encoding"))?,
+        );
+    }
+    let mut bases = vec![];
+    for _ in 0..len {
+        let mut repr = C::Repr::default();
+        reader.read_exact(repr.as_mut())?;
+        bases.push(
+            Option::from(C::from_bytes(&repr)).ok_or_else(|| invalid("invalid point encoding"))?,
+        );
+    }
+    Ok((coeffs, bases))
+}
+
+/// The widest window accepted by [`cpu_multiexp_with_window`]. Each thread
+/// allocates $2^c - 1$ buckets and sums them once per segment, which for wider
+/// windows costs more than the additions they save on any realistic input.
+pub const MAX_MSM_WINDOW_BITS: usize = 16;
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], but
+/// with an explicit Pippenger window size `c` instead of the heuristic one.
+///
+/// Windows are capped at [`MAX_MSM_WINDOW_BITS`] rather than allowed up to
+/// `C::Scalar::NUM_BITS`: a window of `c` bits takes $2^c - 1$ buckets per
+/// thread, which cannot be allocated for windows anywhere near the width of a
+/// scalar.
+///
+/// This function will panic if coeffs and bases have a different length, or
+/// if `c` is not between 1 and the smaller of `C::Scalar::NUM_BITS` and
+/// [`MAX_MSM_WINDOW_BITS`].
+pub fn cpu_multiexp_with_window<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    c: usize,
+) -> C::Curve {
+    let max_c = std::cmp::min(C::Scalar::NUM_BITS as usize, MAX_MSM_WINDOW_BITS);
+    assert!(
+        (1..=max_c).contains(&c),
+        "window size ({}) must be between 1 and {}",
+        c,
+        max_c
+    );
+
+    multiexp_timed_with_window(coeffs, bases, c).0
+}
+
+fn multiexp_timed_with_window<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    c: usize,
+) -> (C::Curve, OpStats) {
     assert_eq!(coeffs.len(), bases.len());
-    
-    let mut stat_collector = MSMLoggingInfo{
-        num_coeffs: format!("{}", coeffs.len() as u32),
-        msm_duration: String::new(),
-    };
 
-    let num_threads = multicore::current_num_threads();
     let start_time = Instant::now();
-    let result = if coeffs.len() > num_threads {
+    let coeffs = scalars_to_reprs(coeffs);
+    let result = multiexp_reprs(&coeffs, bases, c);
+
+    (result, record_msm_stats(coeffs.len(), start_time))
+}
+
+fn multiexp_reprs<C: CurveAffine>(
+    coeffs: &[<C::Scalar as PrimeField>::Repr],
+    bases: &[C],
+    c: usize,
+) -> C::Curve {
+    let num_threads = multicore::current_num_threads();
+    if msm_is_parallel(coeffs.len()) {
         let chunk = coeffs.len() / num_threads;
         let num_chunks = coeffs.chunks(chunk).len();
         let mut results = vec![C::Curve::identity(); num_chunks];
@@ -269,439 +1604,4666 @@ pub fn best_multiexp_cpu<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C
                 .zip(results.iter_mut())
             {
                 scope.spawn(move |_| {
-                    multiexp_serial(coeffs, bases, acc);
+                    multiexp_serial(coeffs, bases, acc, c);
                 });
             }
         });
 
         results.iter().fold(C::Curve::identity(), |a, b| a + b)
     } else {
-
         let mut acc = C::Curve::identity();
-        multiexp_serial(coeffs, bases, &mut acc);
+        multiexp_serial(coeffs, bases, &mut acc, c);
         acc
+    }
+}
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], but
+/// gives up and returns `None` once `cancel` is set, e.g. when the client
+/// waiting for the result has gone away. Each thread checks `cancel` before
+/// each window of its chunk of the inputs, so cancellation takes effect within
+/// one window's worth of work.
+///
+/// This function will panic if coeffs and bases have a different length.
+pub fn cpu_multiexp_cancellable<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    cancel: &AtomicBool,
+) -> Option<C::Curve> {
+    multiexp_cancellable_with(coeffs, bases, cancel, || ())
+}
+
+/// Performs [`cpu_multiexp_cancellable`], calling `after_window` each time a
+/// thread has processed a window, so that tests can cancel it partway.
+fn multiexp_cancellable_with<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+    cancel: &AtomicBool,
+    after_window: impl Fn() + Sync,
+) -> Option<C::Curve> {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let c = parallel_multiexp_window_size(coeffs.len());
+    let segments = (C::Scalar::NUM_BITS as usize / c) + 1;
+    let coeffs = scalars_to_reprs(coeffs);
+    let results = parallelize_map(&coeffs, |coeffs, start| {
+        let n = coeffs.len();
+        let bases = &bases[start..start + n];
+        let digits = window_digits::<C::Scalar>(coeffs, c);
+        let mut acc = C::Curve::identity();
+        for segment in (0..segments).rev() {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            multiexp_serial_segment(&digits[segment * n..(segment + 1) * n], bases, &mut acc, c);
+            after_window();
+        }
+        Some(acc)
+    });
+
+    results
+        .into_iter()
+        .try_fold(C::Curve::identity(), |acc, result| Some(acc + result?))
+}
+
+/// Builds the [`OpStats`] of a CPU MSM of `n` terms started at `start_time`,
+/// logging them if profiling is enabled.
+fn record_msm_stats(n: usize, start_time: Instant) -> OpStats {
+    let stats = OpStats {
+        duration: start_time.elapsed(),
+        device: Device::Cpu,
+        size: n,
     };
 
-    let total_msm_time = start_time.elapsed();
-    stat_collector.msm_duration = format!("{:?}", total_msm_time.as_millis());
-    // Handle potential logging errors
-    if let Err(e) = log_msm_stats(stat_collector) {
-        eprintln!("Failed to log MSM stats: {}", e);
+    #[cfg(feature = "profiling")]
+    if profiling_enabled() {
+        record_profiled_op(("msm", n, stats.device), stats.duration, || {
+            let stat_collector = MSMLoggingInfo {
+                num_coeffs: format!("{}", stats.size as u32),
+                msm_duration: format!("{:?}", stats.duration.as_millis()),
+                label: profiling_label(),
+            };
+            if let Err(e) = log_msm_stats(stat_collector) {
+                report_profiling_error(ProfilingError::Msm(e));
+            }
+        });
     }
 
-    result
+    stats
+}
 
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], over
+/// 256-bit integers that may exceed the order $r$ of the scalar field.
+///
+/// Each `wide` value is given as four little-endian 64-bit limbs, and is
+/// reduced to the scalar $\sum_i \text{wide}_i \cdot 2^{64 i} \bmod r$ by
+/// evaluating this sum with Horner's rule in the scalar field, before the
+/// multi-exponentiation.
+///
+/// This function will panic if wide and bases have a different length.
+pub fn cpu_multiexp_reduce<C: CurveAffine>(wide: &[[u64; 4]], bases: &[C]) -> C::Curve {
+    assert_eq!(wide.len(), bases.len());
 
+    let two_64 = C::Scalar::from(1 << 32).square();
+    let mut coeffs = vec![C::Scalar::ZERO; wide.len()];
+    parallelize(&mut coeffs, |coeffs, start| {
+        for (coeff, wide) in coeffs.iter_mut().zip(wide[start..].iter()) {
+            *coeff = wide
+                .iter()
+                .rev()
+                .fold(C::Scalar::ZERO, |acc, limb| acc * two_64 + C::Scalar::from(*limb));
+        }
+    });
 
+    best_multiexp_cpu(&coeffs, bases)
 }
 
-/// Performs a radix-$2$ Fast-Fourier Transformation (FFT) on a vector of size
-/// $n = 2^k$, when provided `log_n` = $k$ and an element of multiplicative
-/// order $n$ called `omega` ($\omega$). The result is that the vector `a`, when
-/// interpreted as the coefficients of a polynomial of degree $n - 1$, is
-/// transformed into the evaluations of this polynomial at each of the $n$
-/// distinct powers of $\omega$. This transformation is invertible by providing
-/// $\omega^{-1}$ in place of $\omega$ and dividing each resulting field element
-/// by $n$.
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`],
+/// optimized for inputs where many of the scalars are zero or one.
 ///
-/// This will use multithreading if beneficial.
-pub fn best_fft<Scalar: Field, G: FftGroup<Scalar>>(a: &mut [G], omega: Scalar, log_n: u32) {
-    
-    let mut stat_collector = FFTLoggingInfo::new(
-        a.len() as u32,
-        log_n,
-        0.0, // placeholder for fft_duration
-        "cpu"
+/// This function will panic if coeffs and bases have a different length.
+///
+/// Terms with a zero scalar are dropped, and bases with a scalar of one are
+/// summed directly, so that only the remaining terms go through the
+/// multi-exponentiation.
+pub fn cpu_multiexp_sparse<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let mut one_bases = vec![];
+    let mut rest_coeffs = vec![];
+    let mut rest_bases = vec![];
+    for (coeff, base) in coeffs.iter().zip(bases.iter()) {
+        if coeff.is_zero_vartime() {
+            continue;
+        } else if *coeff == C::Scalar::ONE {
+            one_bases.push(*base);
+        } else {
+            rest_coeffs.push(*coeff);
+            rest_bases.push(*base);
+        }
+    }
+
+    let num_threads = multicore::current_num_threads();
+    let chunk_size = std::cmp::max((one_bases.len() + num_threads - 1) / num_threads, 1);
+    let mut parts = vec![C::Curve::identity(); num_threads];
+    multicore::scope(|scope| {
+        for (out, one_bases) in parts.iter_mut().zip(one_bases.chunks(chunk_size)) {
+            scope.spawn(move |_| {
+                for base in one_bases {
+                    *out += *base;
+                }
+            });
+        }
+    });
+
+    parts.iter().fold(best_multiexp_cpu(&rest_coeffs, &rest_bases), |acc, part| acc + part)
+}
+
+/// The number of segments [`cpu_multiexp_deterministic`] splits its input
+/// into, regardless of the number of threads.
+const DETERMINISTIC_MULTIEXP_SEGMENTS: usize = 64;
+
+/// Performs a multi-exponentiation operation like [`best_multiexp_cpu`], but
+/// with a work split that does not depend on the number of threads.
+///
+/// This function will panic if coeffs and bases have a different length.
+///
+/// The input is always split into the same power-of-two number of segments,
+/// each with the same window size, and the partial results are combined by a
+/// pairwise tree reduction in a fixed order. The segments are only spread
+/// across threads above the threshold of [`set_msm_parallel_threshold`], which
+/// does not change how they are split. The result equals that of
+/// [`best_multiexp_cpu`].
+pub fn cpu_multiexp_deterministic<C: CurveAffine>(coeffs: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(coeffs.len(), bases.len());
+
+    let start_time = Instant::now();
+    let coeffs = scalars_to_reprs(coeffs);
+    let segment_len = std::cmp::max(
+        (coeffs.len() + DETERMINISTIC_MULTIEXP_SEGMENTS - 1) / DETERMINISTIC_MULTIEXP_SEGMENTS,
+        1,
+    );
+    let c = multiexp_window_size(segment_len);
+
+    let mut results = vec![C::Curve::identity(); (coeffs.len() + segment_len - 1) / segment_len];
+    let segments = coeffs
+        .chunks(segment_len)
+        .zip(bases.chunks(segment_len))
+        .zip(results.iter_mut());
+    if msm_is_parallel(coeffs.len()) {
+        multicore::scope(|scope| {
+            for ((coeffs, bases), acc) in segments {
+                scope.spawn(move |_| {
+                    multiexp_serial(coeffs, bases, acc, c);
+                });
+            }
+        });
+    } else {
+        for ((coeffs, bases), acc) in segments {
+            multiexp_serial(coeffs, bases, acc, c);
+        }
+    }
+
+    while results.len() > 1 {
+        results = results
+            .chunks(2)
+            .map(|pair| pair.iter().fold(C::Curve::identity(), |acc, p| acc + p))
+            .collect();
+    }
+    record_msm_stats(coeffs.len(), start_time);
+
+    results.first().copied().unwrap_or_else(C::Curve::identity)
+}
+
+/// Performs several independent multi-exponentiation operations at once,
+/// returning one result per job in the same order as `jobs`.
+///
+/// This function will panic if the coeffs and bases of any job have a
+/// different length.
+///
+/// All jobs share a single thread scope, so that many small MSMs run
+/// concurrently instead of one after another.
+pub fn best_multiexp_batch<C: CurveAffine>(jobs: &[(&[C::Scalar], &[C])]) -> Vec<C::Curve> {
+    for (coeffs, bases) in jobs {
+        assert_eq!(coeffs.len(), bases.len());
+    }
+
+    let total_len: usize = jobs.iter().map(|(coeffs, _)| coeffs.len()).sum();
+    let num_threads = multicore::current_num_threads();
+    let chunk = std::cmp::max(total_len / num_threads, 1);
+
+    let mut results: Vec<Vec<C::Curve>> = jobs
+        .iter()
+        .map(|(coeffs, _)| vec![C::Curve::identity(); (coeffs.len() + chunk - 1) / chunk])
+        .collect();
+    multicore::scope(|scope| {
+        for (&(coeffs, bases), results) in jobs.iter().zip(results.iter_mut()) {
+            for ((coeffs, bases), acc) in coeffs
+                .chunks(chunk)
+                .zip(bases.chunks(chunk))
+                .zip(results.iter_mut())
+            {
+                scope.spawn(move |_| {
+                    let coeffs = scalars_to_reprs_serial(coeffs);
+                    multiexp_serial(&coeffs, bases, acc, multiexp_window_size(coeffs.len()));
+                });
+            }
+        }
+    });
+
+    results
+        .iter()
+        .map(|results| results.iter().fold(C::Curve::identity(), |a, b| a + b))
+        .collect()
+}
+
+/// Performs each of the multi-exponentiations in `jobs` like
+/// [`best_multiexp_batch`], and returns the results in affine form.
+///
+/// All of the results are normalized together with a single
+/// `batch_normalize`, which costs one field inversion in total rather than one
+/// per result.
+pub fn best_multiexp_affine_batch<C: CurveAffine>(jobs: &[(&[C::Scalar], &[C])]) -> Vec<C> {
+    let results = best_multiexp_batch(jobs);
+    let mut affine = vec![C::identity(); results.len()];
+    C::Curve::batch_normalize(&results, &mut affine);
+    affine
+}
+
+/// A fixed set of bases, such as those of an SRS, against which many
+/// multi-exponentiations are performed.
+///
+/// When the context is built, the Pippenger window size $c$ is chosen from the
+/// number of bases, and every base $G_i$ is shifted by every segment $s$ of the
+/// scalars, as $2^{cs} G_i$. Each [`MsmContext::msm`] then adds the windows of
+/// all the segments into a single set of buckets over the shifted bases, which
+/// is summed once per thread, rather than once per segment, and needs no
+/// doublings between segments.
+///
+/// The shifted bases take `C::Scalar::NUM_BITS / c + 1` times the memory of the
+/// bases themselves.
+#[derive(Clone, Debug)]
+pub struct MsmContext<'a, C: CurveAffine> {
+    bases: &'a [C],
+    c: usize,
+    /// The shifted bases $2^{cs} G_i$, segment by segment, as the digits of
+    /// [`window_digits`] are laid out.
+    shifted_bases: Vec<C>,
+}
+
+impl<'a, C: CurveAffine> MsmContext<'a, C> {
+    /// Creates a context for multi-exponentiations over `bases`.
+    pub fn new(bases: &'a [C]) -> Self {
+        let n = bases.len();
+        let c = parallel_multiexp_window_size(n);
+        let segments = (C::Scalar::NUM_BITS as usize / c) + 1;
+
+        let mut shifted_bases = vec![C::identity(); n * segments];
+        let mut current: Vec<C::Curve> = bases.iter().map(|base| base.to_curve()).collect();
+        for (segment, shifted) in shifted_bases.chunks_mut(std::cmp::max(n, 1)).enumerate() {
+            if segment > 0 {
+                parallelize(&mut current, |current, _| {
+                    for point in current.iter_mut() {
+                        for _ in 0..c {
+                            *point = point.double();
+                        }
+                    }
+                });
+            }
+            parallelize(shifted, |shifted, start| {
+                C::Curve::batch_normalize(&current[start..start + shifted.len()], shifted);
+            });
+        }
+
+        MsmContext {
+            bases,
+            c,
+            shifted_bases,
+        }
+    }
+
+    /// Returns the bases of this context.
+    pub fn bases(&self) -> &'a [C] {
+        self.bases
+    }
+
+    /// Performs a multi-exponentiation of `coeffs` against the bases of this
+    /// context, with the same result as [`best_multiexp_cpu`].
+    ///
+    /// This function will panic if coeffs and the bases have a different
+    /// length.
+    pub fn msm(&self, coeffs: &[C::Scalar]) -> C::Curve {
+        assert_eq!(coeffs.len(), self.bases.len());
+
+        let start_time = Instant::now();
+        let reprs = scalars_to_reprs(coeffs);
+        let digits = window_digits::<C::Scalar>(&reprs, self.c);
+        // Starting from the identity, the doublings of `multiexp_serial_segment`
+        // have no effect, and it only fills and sums the buckets.
+        let result = parallelize_map(&digits, |digits, start| {
+            let mut acc = C::Curve::identity();
+            let shifted_bases = &self.shifted_bases[start..start + digits.len()];
+            multiexp_serial_segment(digits, shifted_bases, &mut acc, self.c);
+            acc
+        })
+        .into_iter()
+        .fold(C::Curve::identity(), |acc, part| acc + part);
+        record_msm_stats(coeffs.len(), start_time);
+
+        result
+    }
+}
+
+/// The default number of pairs an [`MsmAccumulator`] buffers before flushing.
+const MSM_ACCUMULATOR_CAPACITY: usize = 1 << 12;
+
+/// Accumulates a multi-exponentiation over `(scalar, base)` pairs that arrive
+/// incrementally, without holding all of them in memory at once.
+///
+/// Pairs are buffered until `capacity` of them have been pushed, at which point
+/// the buffered terms are evaluated with a serial Pippenger MSM and added to a
+/// running sum.
+#[derive(Debug)]
+pub struct MsmAccumulator<C: CurveAffine> {
+    coeffs: Vec<C::Scalar>,
+    bases: Vec<C>,
+    acc: C::Curve,
+    capacity: usize,
+}
+
+impl<C: CurveAffine> Default for MsmAccumulator<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: CurveAffine> MsmAccumulator<C> {
+    /// Creates an empty accumulator with the default buffer capacity.
+    pub fn new() -> Self {
+        Self::with_capacity(MSM_ACCUMULATOR_CAPACITY)
+    }
+
+    /// Creates an empty accumulator that flushes after every `capacity` pairs.
+    ///
+    /// This function will panic if `capacity` is zero.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be nonzero");
+        MsmAccumulator {
+            coeffs: Vec::with_capacity(capacity),
+            bases: Vec::with_capacity(capacity),
+            acc: C::Curve::identity(),
+            capacity,
+        }
+    }
+
+    /// Adds `scalar * base` to the multi-exponentiation.
+    pub fn push(&mut self, scalar: C::Scalar, base: C) {
+        self.coeffs.push(scalar);
+        self.bases.push(base);
+        if self.coeffs.len() == self.capacity {
+            self.flush();
+        }
+    }
+
+    /// Returns the sum of all the terms pushed so far.
+    pub fn finalize(mut self) -> C::Curve {
+        self.flush();
+        self.acc
+    }
+
+    fn flush(&mut self) {
+        if self.coeffs.is_empty() {
+            return;
+        }
+
+        let coeffs = scalars_to_reprs_serial(&self.coeffs);
+        let mut partial = C::Curve::identity();
+        multiexp_serial(
+            &coeffs,
+            &self.bases,
+            &mut partial,
+            multiexp_window_size(coeffs.len()),
+        );
+        self.acc += partial;
+
+        self.coeffs.clear();
+        self.bases.clear();
+    }
+}
+
+impl<C: CurveAffine> Extend<(C::Scalar, C)> for MsmAccumulator<C> {
+    fn extend<I: IntoIterator<Item = (C::Scalar, C)>>(&mut self, iter: I) {
+        for (scalar, base) in iter {
+            self.push(scalar, base);
+        }
+    }
+}
+
+/// Performs a radix-$2$ Fast-Fourier Transformation (FFT) on a vector of size
+/// $n = 2^k$, when provided `log_n` = $k$ and an element of multiplicative
+/// order $n$ called `omega` ($\omega$). The result is that the vector `a`, when
+/// interpreted as the coefficients of a polynomial of degree $n - 1$, is
+/// transformed into the evaluations of this polynomial at each of the $n$
+/// distinct powers of $\omega$. This transformation is invertible by providing
+/// $\omega^{-1}$ in place of $\omega$ and dividing each resulting field element
+/// by $n$.
+///
+/// This will use multithreading if beneficial.
+pub fn best_fft<Scalar: Field, G: FftGroupLocal<Scalar>>(a: &mut [G], omega: Scalar, log_n: u32) {
+    fft_timed(a, omega, log_n);
+}
+
+/// An error returned by [`try_best_fft`] when the input does not describe a
+/// valid FFT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FftError {
+    /// The input slice does not have length $2^{log\_n}$.
+    BadLength {
+        /// The length of the input slice.
+        got: usize,
+        /// The length $2^{log\_n}$ required by `log_n`.
+        expected: usize,
+    },
+    /// $2^{log\_n}$ does not fit in a `usize`.
+    DomainTooLarge {
+        /// The requested `log_n`.
+        log_n: u32,
+    },
+}
+
+impl std::fmt::Display for FftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FftError::BadLength { got, expected } => write!(
+                f,
+                "FFT input has length {}, but the domain has size {}",
+                got, expected
+            ),
+            FftError::DomainTooLarge { log_n } => {
+                write!(f, "FFT domain of size 2^{} is too large", log_n)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FftError {}
+
+/// Performs the same FFT as [`best_fft`], but returns an error instead of
+/// panicking if `a` does not have length $2^k$, where `log_n` = $k$.
+pub fn try_best_fft<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) -> Result<(), FftError> {
+    let expected = 1usize
+        .checked_shl(log_n)
+        .ok_or(FftError::DomainTooLarge { log_n })?;
+    if a.len() != expected {
+        return Err(FftError::BadLength {
+            got: a.len(),
+            expected,
+        });
+    }
+
+    best_fft(a, omega, log_n);
+    Ok(())
+}
+
+/// The largest `log_n` for which [`best_fft_checked`] compares against a naive
+/// DFT.
+#[cfg(debug_assertions)]
+const FFT_CHECK_MAX_LOG_N: u32 = 10;
+
+/// Performs the same FFT as [`best_fft`], after checking that `omega` has
+/// multiplicative order exactly $2^k$, where `log_n` = $k$. In builds with
+/// debug assertions, for `log_n` of at most 10, the result is also compared
+/// against a naive $O(n^2)$ DFT. This function panics if either check fails.
+pub fn best_fft_checked<Scalar: Field, G: FftGroup<Scalar> + PartialEq + std::fmt::Debug>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) {
+    assert_eq!(a.len(), 1 << log_n);
+    if log_n == 0 {
+        assert_eq!(omega, Scalar::ONE, "omega must have order 1");
+    } else {
+        // omega^{2^{k - 1}} must be a square root of one other than one.
+        let half_order_power = (1..log_n).fold(omega, |w, _| w.square());
+        assert!(
+            half_order_power != Scalar::ONE && half_order_power.square() == Scalar::ONE,
+            "omega must have order 2^{}",
+            log_n
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    let expected = if log_n <= FFT_CHECK_MAX_LOG_N {
+        Some(naive_dft(a, omega))
+    } else {
+        None
+    };
+
+    best_fft(a, omega, log_n);
+
+    #[cfg(debug_assertions)]
+    {
+        if let Some(expected) = expected {
+            assert_eq!(&a[..], &expected[..], "FFT result does not match the naive DFT");
+        }
+    }
+}
+
+/// Computes the DFT of `a` directly from its definition.
+#[cfg(debug_assertions)]
+fn naive_dft<Scalar: Field, G: FftGroup<Scalar>>(a: &[G], omega: Scalar) -> Vec<G> {
+    let zero = a[0] * &Scalar::ZERO;
+    (0..a.len())
+        .map(|k| {
+            let omega_k = omega.pow_vartime([k as u64, 0, 0, 0]);
+            let mut w = Scalar::ONE;
+            a.iter().fold(zero, |acc, a_j| {
+                let term = *a_j * &w;
+                w *= omega_k;
+                acc + &term
+            })
+        })
+        .collect()
+}
+
+/// Performs the same FFT as [`best_fft`], and returns how long it took.
+pub fn fft_timed<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) -> OpStats {
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    let twiddles = compute_twiddles(omega, log_n);
+    fft_inner(a, log_n, &twiddles);
+
+    record_fft_stats(n, log_n, timer)
+}
+
+/// Computes the twiddle factors $\omega^0, \omega^1, \dots, \omega^{n/2 - 1}$
+/// used by an FFT of size $n = 2^k$, when provided `log_n` = $k$.
+pub fn compute_twiddles<F: Field>(omega: F, log_n: u32) -> Vec<F> {
+    let mut twiddles = vec![];
+    fill_twiddles(omega, log_n, &mut twiddles);
+    twiddles
+}
+
+/// Replaces the contents of `twiddles` with the output of [`compute_twiddles`],
+/// reusing its allocation.
+///
+/// The powers are computed in parallel: each chunk starts from
+/// $\omega^{start}$, computed with `pow_vartime`, and then multiplies by
+/// $\omega$ in sequence, as [`serial::compute_twiddles`] does.
+fn fill_twiddles<F: Field>(omega: F, log_n: u32, twiddles: &mut Vec<F>) {
+    let n = 1usize << log_n;
+    twiddles.clear();
+    twiddles.resize(n / 2, F::ZERO);
+    parallelize(twiddles, |twiddles, start| {
+        let mut w = omega.pow_vartime([start as u64]);
+        for tw in twiddles.iter_mut() {
+            *tw = w;
+            w *= &omega;
+        }
+    });
+}
+
+/// Performs the same FFT as [`best_fft`], but stores the twiddle factors in
+/// `scratch` instead of allocating them. `scratch` is cleared and refilled
+/// with $n / 2$ elements, so it can be reused across calls to avoid repeated
+/// allocations.
+pub fn fft_into<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+    scratch: &mut Vec<Scalar>,
+) {
+    fill_twiddles(omega, log_n, scratch);
+    fft_with_twiddles(a, log_n, scratch);
+}
+
+/// Performs the same FFT as [`best_fft`], and produces the same output, but
+/// fuses each pair of consecutive radix-2 rounds into a single radix-4 round
+/// when `log_n` is even. This halves the number of passes over `a`. For odd
+/// `log_n`, this falls back to [`best_fft`].
+///
+/// This will use multithreading if beneficial.
+pub fn best_fft_radix4<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) {
+    if log_n % 2 == 1 {
+        return best_fft(a, omega, log_n);
+    }
+
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    let twiddles = compute_twiddles(omega, log_n);
+    let threads = multicore::current_num_threads();
+
+    bitreverse_permute(a, log_n);
+    // Each round combines the radix-2 rounds over blocks of 2m and 4m elements.
+    let mut m = 1;
+    while m < n {
+        radix4_round(a, m, &twiddles, threads);
+        m *= 4;
+    }
+
+    record_fft_stats(n, log_n, timer);
+}
+
+/// Performs a radix-4 round of [`best_fft_radix4`] over each block of `4 * m`
+/// elements of `a`, splitting either the blocks or, when there are fewer blocks
+/// than threads, the butterflies of each block across threads.
+fn radix4_round<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    m: usize,
+    twiddles: &[Scalar],
+    threads: usize,
+) {
+    fn quarters<G>(block: &mut [G], m: usize) -> [&mut [G]; 4] {
+        let (lo, hi) = block.split_at_mut(2 * m);
+        let (q0, q1) = lo.split_at_mut(m);
+        let (q2, q3) = hi.split_at_mut(m);
+        [q0, q1, q2, q3]
+    }
+
+    let stride = a.len() / (4 * m);
+    if stride >= threads {
+        let blocks_per_thread = (stride + threads - 1) / threads;
+        multicore::scope(|scope| {
+            for chunk in a.chunks_mut(blocks_per_thread * 4 * m) {
+                scope.spawn(move |_| {
+                    for block in chunk.chunks_mut(4 * m) {
+                        radix4_butterflies(quarters(block, m), 0, m, stride, twiddles);
+                    }
+                });
+            }
+        });
+    } else {
+        let chunk = (m + threads - 1) / threads;
+        for block in a.chunks_mut(4 * m) {
+            let [q0, q1, q2, q3] = quarters(block, m);
+            multicore::scope(|scope| {
+                for (i, (((q0, q1), q2), q3)) in q0
+                    .chunks_mut(chunk)
+                    .zip(q1.chunks_mut(chunk))
+                    .zip(q2.chunks_mut(chunk))
+                    .zip(q3.chunks_mut(chunk))
+                    .enumerate()
+                {
+                    scope.spawn(move |_| {
+                        radix4_butterflies([q0, q1, q2, q3], i * chunk, m, stride, twiddles);
+                    });
+                }
+            });
+        }
+    }
+}
+
+/// Applies the radix-4 butterflies to the `j`-th elements, from `j_start`
+/// onwards, of the four quarters of a block of `4 * m` elements. `stride` is
+/// the number of such blocks, so that $\omega_{4m}^j$ is `twiddles[j * stride]`.
+fn radix4_butterflies<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    [q0, q1, q2, q3]: [&mut [G]; 4],
+    j_start: usize,
+    m: usize,
+    stride: usize,
+    twiddles: &[Scalar],
+) {
+    for (j, (((a0, a1), a2), a3)) in q0
+        .iter_mut()
+        .zip(q1.iter_mut())
+        .zip(q2.iter_mut())
+        .zip(q3.iter_mut())
+        .enumerate()
+    {
+        let j = j_start + j;
+        let w_2m = &twiddles[2 * j * stride];
+        let w_4m = &twiddles[j * stride];
+        let w_4m_shifted = &twiddles[(j + m) * stride];
+
+        // Radix-2 round over the blocks of 2m elements
+        let t = *a1 * w_2m;
+        let b0 = *a0 + &t;
+        let b1 = *a0 - &t;
+        let t = *a3 * w_2m;
+        let b2 = *a2 + &t;
+        let b3 = *a2 - &t;
+
+        // Radix-2 round over the block of 4m elements
+        let t = b2 * w_4m;
+        *a0 = b0 + &t;
+        *a2 = b0 - &t;
+        let t = b3 * w_4m_shifted;
+        *a1 = b1 + &t;
+        *a3 = b1 - &t;
+    }
+}
+
+/// Performs the same FFT as [`best_fft`], and produces the same output, for a
+/// vector `a` whose coefficients at indices `nonzero_len` and above are all
+/// zero.
+///
+/// With $m$ the smallest power of two that is at least `nonzero_len`, and
+/// $r = n / m$, the first $\log_2 r$ rounds of a decimation-in-frequency FFT
+/// only copy the nonzero coefficients around. This skips them and instead
+/// computes $r$ independent FFTs of size $m$ over $\omega^r$, one for each
+/// residue $c$ of the output index modulo $r$, on the inputs
+/// $a_t \cdot \omega^{ct}$. The cost is $O(n \log m)$ instead of
+/// $O(n \log n)$.
+///
+/// This function will panic if `a` does not have $2^k$ elements for
+/// `log_n` = $k$, or if `nonzero_len` is larger than `a`.
+pub fn truncated_fft<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+    nonzero_len: usize,
+) {
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+    assert!(nonzero_len <= n);
+
+    let log_m = log2_ceil(nonzero_len.max(1));
+    if log_m + 1 >= log_n {
+        // Skipping a single round is not worth the extra copy.
+        return best_fft(a, omega, log_n);
+    }
+
+    let timer = Instant::now();
+
+    let m = 1 << log_m;
+    let r = n / m;
+    let twiddles = compute_twiddles(omega.pow_vartime([r as u64]), log_m);
+
+    // Block c holds the size m transform for the outputs at indices c + r * u.
+    let mut blocks = a[..m].repeat(r);
+    let num_threads = multicore::current_num_threads();
+    let blocks_per_thread = (r + num_threads - 1) / num_threads;
+    multicore::scope(|scope| {
+        for (chunk_idx, chunk) in blocks.chunks_mut(blocks_per_thread * m).enumerate() {
+            let twiddles = &twiddles;
+            scope.spawn(move |_| {
+                for (i, block) in chunk.chunks_mut(m).enumerate() {
+                    let c = chunk_idx * blocks_per_thread + i;
+                    let step = omega.pow_vartime([c as u64]);
+                    let mut w = Scalar::ONE;
+                    for coeff in block.iter_mut() {
+                        *coeff *= &w;
+                        w *= &step;
+                    }
+                    bitreverse_permute(block, log_m);
+                    serial::butterfly_rounds(block, log_m, twiddles, 1);
+                }
+            });
+        }
+    });
+
+    parallelize(a, |a, start| {
+        for (i, out) in a.iter_mut().enumerate() {
+            let index = start + i;
+            *out = blocks[(index % r) * m + index / r];
+        }
+    });
+
+    record_fft_stats(n, log_n, timer);
+}
+
+/// Performs the same FFT as [`best_fft`] on each of `batches`, which must all
+/// have size $n = 2^k$ for `log_n` = $k$. The twiddle factors for `omega` are
+/// computed once and shared across all of the transforms.
+pub fn best_fft_many<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    batches: &mut [&mut [G]],
+    omega: Scalar,
+    log_n: u32,
+) {
+    let twiddles = compute_twiddles(omega, log_n);
+    for a in batches.iter_mut() {
+        fft_with_twiddles(a, log_n, &twiddles);
+    }
+}
+
+/// Performs the same FFT as [`best_fft`], using twiddle factors that were
+/// precomputed with [`compute_twiddles`] for the desired `omega` and `log_n`.
+///
+/// This function will panic if `a` does not have $2^k$ elements, or if
+/// `twiddles` does not have $2^{k-1}$ elements, where `log_n` = $k$.
+pub fn fft_with_twiddles<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    log_n: u32,
+    twiddles: &[Scalar],
+) {
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+    assert_eq!(twiddles.len(), n / 2);
+
+    fft_inner(a, log_n, twiddles);
+
+    record_fft_stats(n, log_n, timer);
+}
+
+fn fft_inner<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    log_n: u32,
+    twiddles: &[Scalar],
+) {
+    let threads = multicore::current_num_threads();
+    let log_threads = log2_floor(threads);
+    let n = a.len();
+
+    bitreverse_permute(a, log_n);
+
+    if log_n <= log_threads {
+        serial::butterfly_rounds(a, log_n, twiddles, 1);
+    } else {
+        recursive_butterfly_arithmetic(a, n, 1, twiddles)
+    }
+}
+
+/// Builds the [`OpStats`] of a CPU FFT started at `timer`, logging them if
+/// profiling is enabled.
+fn record_fft_stats(n: usize, log_n: u32, timer: Instant) -> OpStats {
+    let stats = OpStats {
+        duration: timer.elapsed(),
+        device: Device::Cpu,
+        size: n,
+    };
+
+    #[cfg(feature = "profiling")]
+    if profiling_enabled() {
+        record_profiled_op(("fft", n, stats.device), stats.duration, || {
+            let stat_collector =
+                FFTLoggingInfo::new(n as u32, log_n, stats.duration.as_secs_f64(), "cpu");
+            if let Err(e) = log_fft_stats(stat_collector) {
+                report_profiling_error(ProfilingError::Fft(e));
+            }
+        });
+    }
+
+    stats
+}
+
+/// A radix-2 FFT of size $n = 2^k$ over a fixed `omega`, with its twiddle
+/// factors precomputed, which can be run one butterfly pass at a time. This
+/// lets a caller interleave the passes of a transform with other work, and
+/// reuse the plan across several transforms of the same shape.
+///
+/// Running all of the passes in order produces the same output as
+/// [`best_fft`].
+#[derive(Clone, Debug)]
+pub struct FftPlan<Scalar> {
+    twiddles: Vec<Scalar>,
+    log_n: u32,
+    n: usize,
+}
+
+impl<Scalar: Field> FftPlan<Scalar> {
+    /// Creates a plan for FFTs of size $2^k$, when provided `log_n` = $k$, and
+    /// an `omega` of multiplicative order $2^k$.
+    pub fn new(omega: Scalar, log_n: u32) -> Self {
+        FftPlan {
+            twiddles: compute_twiddles(omega, log_n),
+            log_n,
+            n: 1 << log_n,
+        }
+    }
+
+    /// Returns the number of passes of the transform, which is `log_n`.
+    pub fn num_passes(&self) -> u32 {
+        self.log_n
+    }
+
+    /// Performs pass number `pass` of the transform on `a`, where pass 0 also
+    /// permutes `a` into bit-reversed order. The passes of one transform must
+    /// be run in order, from 0 to [`num_passes`](Self::num_passes) - 1, and
+    /// `a` only holds the result once all of them have been run.
+    ///
+    /// This function will panic if `a` does not have $n$ elements, or if
+    /// `pass` is out of range.
+    pub fn step<G: FftGroupLocal<Scalar>>(&self, a: &mut [G], pass: u32) {
+        assert_eq!(a.len(), self.n);
+        assert!(pass < self.log_n, "pass {} is out of range", pass);
+
+        if pass == 0 {
+            bitreverse_permute(a, self.log_n);
+        }
+        radix2_round(a, 1 << pass, &self.twiddles, multicore::current_num_threads());
+    }
+
+    /// Performs all of the passes of the transform on `a`.
+    ///
+    /// This function will panic if `a` does not have $n$ elements.
+    pub fn run_all<G: FftGroupLocal<Scalar>>(&self, a: &mut [G]) {
+        let timer = Instant::now();
+
+        assert_eq!(a.len(), self.n);
+        for pass in 0..self.log_n {
+            self.step(a, pass);
+        }
+
+        record_fft_stats(self.n, self.log_n, timer);
+    }
+}
+
+/// Performs a radix-2 butterfly round over each block of `2 * m` elements of
+/// `a`, splitting either the blocks or, when there are fewer blocks than
+/// threads, the butterflies of each block across threads.
+fn radix2_round<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    m: usize,
+    twiddles: &[Scalar],
+    threads: usize,
+) {
+    let stride = a.len() / (2 * m);
+    butterfly_round(a, m, threads, &|left: &mut [G], right: &mut [G], start: usize| {
+        for (i, (a, b)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let mut t = *b;
+            t *= &twiddles[(start + i) * stride];
+            *b = *a;
+            *a += &t;
+            *b -= &t;
+        }
+    });
+}
+
+/// Performs a decimation-in-frequency butterfly round over each block of
+/// `2 * m` elements of `a`, the reverse of [`radix2_round`]: the twiddle
+/// factor is applied after the butterfly rather than before it.
+fn dif_round<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    m: usize,
+    twiddles: &[Scalar],
+    threads: usize,
+) {
+    let stride = a.len() / (2 * m);
+    butterfly_round(a, m, threads, &|left: &mut [G], right: &mut [G], start: usize| {
+        for (i, (a, b)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let mut t = *a;
+            t -= &*b;
+            *a += &*b;
+            t *= &twiddles[(start + i) * stride];
+            *b = t;
+        }
+    });
+}
+
+/// Calls `butterflies` on the halves of each block of `2 * m` elements of `a`,
+/// along with the offset of the first butterfly within the block, splitting
+/// either the blocks or, when there are fewer blocks than threads, the
+/// butterflies of each block across threads.
+fn butterfly_round<G: Send, B: Fn(&mut [G], &mut [G], usize) + Sync>(
+    a: &mut [G],
+    m: usize,
+    threads: usize,
+    butterflies: &B,
+) {
+    let stride = a.len() / (2 * m);
+    if stride >= threads {
+        let blocks_per_thread = (stride + threads - 1) / threads;
+        multicore::scope(|scope| {
+            for chunk in a.chunks_mut(blocks_per_thread * 2 * m) {
+                scope.spawn(move |_| {
+                    for block in chunk.chunks_mut(2 * m) {
+                        let (left, right) = block.split_at_mut(m);
+                        butterflies(left, right, 0);
+                    }
+                });
+            }
+        });
+    } else {
+        let chunk = (m + threads - 1) / threads;
+        for block in a.chunks_mut(2 * m) {
+            let (left, right) = block.split_at_mut(m);
+            multicore::scope(|scope| {
+                for (i, (left, right)) in left
+                    .chunks_mut(chunk)
+                    .zip(right.chunks_mut(chunk))
+                    .enumerate()
+                {
+                    scope.spawn(move |_| butterflies(left, right, i * chunk));
+                }
+            });
+        }
+    }
+}
+
+/// Applies the butterflies of a pass of [`fft_group`] to each `(left, right,
+/// start)` pair of half-blocks, where `start` is the offset of the first
+/// butterfly within its block. The points of all right halves that need a
+/// twiddle multiplication are batch-normalized together, with one inversion.
+fn group_butterflies<C: CurveAffine>(
+    halves: &mut [(&mut [C::Curve], &mut [C::Curve], usize)],
+    twiddles: &[C::Scalar],
+    stride: usize,
+) {
+    let projective: Vec<C::Curve> = halves
+        .iter()
+        .flat_map(|(_, right, start)| right.iter().skip(usize::from(*start == 0)))
+        .copied()
+        .collect();
+    let mut affine = vec![C::identity(); projective.len()];
+    C::Curve::batch_normalize(&projective, &mut affine);
+
+    let mut affine = affine.iter();
+    for (left, right, start) in halves.iter_mut() {
+        for (i, (a, b)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let t = match *start + i {
+                0 => *b,
+                j => *affine.next().unwrap() * twiddles[j * stride],
+            };
+            *b = *a - t;
+            *a += t;
+        }
+    }
+}
+
+/// Performs the same FFT as [`best_fft`] over curve points, as used by
+/// [`g_to_lagrange`], with cheaper twiddle multiplications.
+///
+/// Each butterfly multiplies a point by a twiddle factor, which dominates the
+/// cost of an FFT over points. Here the points to be multiplied in each pass
+/// are first batch-normalized to affine form, once per thread and pass, so that
+/// the double-and-add runs with mixed additions, and multiplications by a
+/// twiddle of one, which make up the whole first pass, are skipped.
+///
+/// This function will panic if `a` does not have $2^{log\_n}$ elements.
+pub fn fft_group<C: CurveAffine>(a: &mut [C::Curve], omega: C::Scalar, log_n: u32) {
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    let twiddles = compute_twiddles(omega, log_n);
+    let threads = multicore::current_num_threads();
+
+    bitreverse_permute(a, log_n);
+    let mut m = 1;
+    while m < n {
+        let stride = n / (2 * m);
+        let twiddles = &twiddles;
+        if stride >= threads {
+            let blocks_per_thread = (stride + threads - 1) / threads;
+            multicore::scope(|scope| {
+                for chunk in a.chunks_mut(blocks_per_thread * 2 * m) {
+                    scope.spawn(move |_| {
+                        let mut halves: Vec<_> = chunk
+                            .chunks_mut(2 * m)
+                            .map(|block| {
+                                let (left, right) = block.split_at_mut(m);
+                                (left, right, 0)
+                            })
+                            .collect();
+                        group_butterflies::<C>(&mut halves, twiddles, stride);
+                    });
+                }
+            });
+        } else {
+            let chunk = (m + threads - 1) / threads;
+            for block in a.chunks_mut(2 * m) {
+                let (left, right) = block.split_at_mut(m);
+                multicore::scope(|scope| {
+                    for (i, (left, right)) in left
+                        .chunks_mut(chunk)
+                        .zip(right.chunks_mut(chunk))
+                        .enumerate()
+                    {
+                        scope.spawn(move |_| {
+                            let mut halves = [(left, right, i * chunk)];
+                            group_butterflies::<C>(&mut halves, twiddles, stride);
+                        });
+                    }
+                });
+            }
+        }
+        m *= 2;
+    }
+
+    record_fft_stats(n, log_n, timer);
+}
+
+/// Performs the same FFT as [`best_fft`], but leaves the result in
+/// bit-reversed order: on return, `a[bitreverse(i, log_n)]` holds the
+/// evaluation at $\omega^i$. This is a decimation-in-frequency transform,
+/// which takes its input in natural order and skips the bit-reversal
+/// permutation, for callers that do not need the evaluations in order, e.g.
+/// because they only multiply them pointwise before
+/// [`ifft_bitreversed_input`].
+///
+/// This function will panic if `a` does not have $2^{log\_n}$ elements.
+pub fn fft_bitreversed_output<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) {
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    let twiddles = compute_twiddles(omega, log_n);
+    let threads = multicore::current_num_threads();
+    let mut m = n / 2;
+    while m >= 1 {
+        dif_round(a, m, &twiddles, threads);
+        m /= 2;
+    }
+
+    record_fft_stats(n, log_n, timer);
+}
+
+/// Performs an inverse FFT like [`best_ifft`], but takes its input in
+/// bit-reversed order, as produced by [`fft_bitreversed_output`]: `a[i]` must
+/// hold the evaluation at $\omega^{bitreverse(i, log\_n)}$. The coefficients
+/// are returned in natural order, without a bit-reversal permutation.
+///
+/// This function will panic if `a` does not have $2^{log\_n}$ elements.
+pub fn ifft_bitreversed_input<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega_inv: Scalar,
+    log_n: u32,
+) {
+    let timer = Instant::now();
+
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    let twiddles = compute_twiddles(omega_inv, log_n);
+    let threads = multicore::current_num_threads();
+    let mut m = 1;
+    while m < n {
+        radix2_round(a, m, &twiddles, threads);
+        m *= 2;
+    }
+    divide_by_n(a, log_n);
+
+    record_fft_stats(n, log_n, timer);
+}
+
+/// Divides each element of `a` by $n = 2^{log\_n}$, the last step of an
+/// inverse FFT.
+fn divide_by_n<Scalar: Field, G: FftGroupLocal<Scalar>>(a: &mut [G], log_n: u32) {
+    let n_inv = (0..log_n)
+        .fold(Scalar::ONE, |acc, _| acc.double())
+        .invert()
+        .unwrap();
+    parallelize(a, |a, _| {
+        for a in a.iter_mut() {
+            *a *= &n_inv;
+        }
+    });
+}
+
+/// Performs the same FFT as [`best_fft`], but gives up once `cancel` is set,
+/// which is checked before each butterfly pass. Returns whether the transform
+/// completed; if it did not, `a` is left partially transformed.
+#[must_use]
+pub fn cpu_fft_cancellable<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+    cancel: &AtomicBool,
+) -> bool {
+    let plan = FftPlan::new(omega, log_n);
+    for pass in 0..plan.num_passes() {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        plan.step(a, pass);
+    }
+    true
+}
+
+/// A cache of FFT twiddle factors keyed by `(omega, log_n)`, so that repeated
+/// FFTs over the same domain do not recompute them. The cache can be shared
+/// across threads.
+#[derive(Debug, Default)]
+pub struct TwiddleCache<Scalar: PrimeField> {
+    twiddles: Mutex<HashMap<(Vec<u8>, u32), Arc<Vec<Scalar>>>>,
+}
+
+impl<Scalar: PrimeField> TwiddleCache<Scalar> {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        TwiddleCache {
+            twiddles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Performs the same FFT as [`best_fft`], reusing the twiddle factors for
+    /// `omega` and `log_n` if they are already cached.
+    pub fn fft_cached<G: FftGroupLocal<Scalar>>(&self, a: &mut [G], omega: Scalar, log_n: u32) {
+        let twiddles = self.get_or_compute(omega, log_n);
+        fft_with_twiddles(a, log_n, &twiddles);
+    }
+
+    /// Removes the twiddle factors for `omega` and `log_n` from the cache.
+    pub fn evict(&self, omega: Scalar, log_n: u32) {
+        let key = (omega.to_repr().as_ref().to_vec(), log_n);
+        self.twiddles.lock().unwrap().remove(&key);
+    }
+
+    /// Removes all twiddle factors from the cache.
+    pub fn clear(&self) {
+        self.twiddles.lock().unwrap().clear();
+    }
+
+    fn get_or_compute(&self, omega: Scalar, log_n: u32) -> Arc<Vec<Scalar>> {
+        let key = (omega.to_repr().as_ref().to_vec(), log_n);
+        if let Some(twiddles) = self.twiddles.lock().unwrap().get(&key) {
+            return twiddles.clone();
+        }
+
+        // Compute outside the lock so that other domains are not blocked.
+        let twiddles = Arc::new(compute_twiddles(omega, log_n));
+        self.twiddles
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(twiddles)
+            .clone()
+    }
+}
+
+/// An FFT algorithm for inputs of a fixed size, as chosen by [`DftPlanner`].
+pub trait DftStrategy<Scalar: Field, G: FftGroupLocal<Scalar>>:
+    std::fmt::Debug + Send + Sync
+{
+    /// Performs the same FFT as [`best_fft`] on `a`, for the size the strategy
+    /// was planned for.
+    fn transform(&self, a: &mut [G], omega: Scalar);
+
+    /// Returns a short name for the algorithm, e.g. for logging.
+    fn name(&self) -> &'static str;
+}
+
+/// Runs [`serial::fft`] on the current thread only.
+#[derive(Clone, Copy, Debug)]
+struct SerialDft {
+    log_n: u32,
+}
+
+impl<Scalar: Field, G: FftGroupLocal<Scalar>> DftStrategy<Scalar, G> for SerialDft {
+    fn transform(&self, a: &mut [G], omega: Scalar) {
+        serial::fft(a, omega, self.log_n)
+    }
+
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+}
+
+/// Runs the multithreaded radix-2 [`best_fft`].
+#[derive(Clone, Copy, Debug)]
+struct Radix2Dft {
+    log_n: u32,
+}
+
+impl<Scalar: Field, G: FftGroupLocal<Scalar>> DftStrategy<Scalar, G> for Radix2Dft {
+    fn transform(&self, a: &mut [G], omega: Scalar) {
+        best_fft(a, omega, self.log_n)
+    }
+
+    fn name(&self) -> &'static str {
+        "radix-2"
+    }
+}
+
+/// Runs the multithreaded [`best_fft_radix4`].
+#[derive(Clone, Copy, Debug)]
+struct Radix4Dft {
+    log_n: u32,
+}
+
+impl<Scalar: Field, G: FftGroupLocal<Scalar>> DftStrategy<Scalar, G> for Radix4Dft {
+    fn transform(&self, a: &mut [G], omega: Scalar) {
+        best_fft_radix4(a, omega, self.log_n)
+    }
+
+    fn name(&self) -> &'static str {
+        "radix-4"
+    }
+}
+
+/// Chooses an FFT algorithm from the size of the input, so that callers need
+/// not hardcode one. Transforms of size up to $2^{serial\_max\_log\_n}$ run
+/// on the current thread with [`serial::fft`], where threading costs more than
+/// it saves; larger transforms of an even `log_n` within the radix-4 range use
+/// [`best_fft_radix4`], and all others [`best_fft`]. All of them produce the
+/// same output.
+///
+/// There is no GPU FFT, so every strategy runs on the CPU.
+#[derive(Clone, Debug)]
+pub struct DftPlanner {
+    serial_max_log_n: u32,
+    radix4_log_n: std::ops::RangeInclusive<u32>,
+}
+
+impl Default for DftPlanner {
+    fn default() -> Self {
+        DftPlanner {
+            serial_max_log_n: 10,
+            radix4_log_n: 12..=22,
+        }
+    }
+}
+
+impl DftPlanner {
+    /// Creates a planner with the default thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the largest `log_n` transformed on the current thread only.
+    pub fn serial_max_log_n(mut self, log_n: u32) -> Self {
+        self.serial_max_log_n = log_n;
+        self
+    }
+
+    /// Sets the range of `log_n` transformed with [`best_fft_radix4`], when
+    /// `log_n` is even.
+    pub fn radix4_log_n(mut self, log_n: std::ops::RangeInclusive<u32>) -> Self {
+        self.radix4_log_n = log_n;
+        self
+    }
+
+    /// Returns the strategy for FFTs of size $2^k$, when provided `log_n` = $k$.
+    pub fn plan<Scalar: Field, G: FftGroupLocal<Scalar>>(
+        &self,
+        log_n: u32,
+    ) -> Box<dyn DftStrategy<Scalar, G>> {
+        if log_n <= self.serial_max_log_n {
+            Box::new(SerialDft { log_n })
+        } else if log_n % 2 == 0 && self.radix4_log_n.contains(&log_n) {
+            Box::new(Radix4Dft { log_n })
+        } else {
+            Box::new(Radix2Dft { log_n })
+        }
+    }
+}
+
+/// The default size below which [`recursive_butterfly_arithmetic`] stops
+/// splitting its work with `multicore::join`.
+pub const DEFAULT_FFT_SERIAL_CUTOFF: usize = 1 << 14;
+
+static FFT_SERIAL_CUTOFF: AtomicUsize = AtomicUsize::new(DEFAULT_FFT_SERIAL_CUTOFF);
+
+/// Sets the size at or below which [`recursive_butterfly_arithmetic`] runs a
+/// sub-transform sequentially instead of recursing with `multicore::join`, as
+/// long as there are already at least as many sub-transforms as threads.
+///
+/// This only trades scheduling overhead against parallelism; the FFT output is
+/// the same for any cutoff. It defaults to [`DEFAULT_FFT_SERIAL_CUTOFF`].
+pub fn set_fft_serial_cutoff(cutoff: usize) {
+    FFT_SERIAL_CUTOFF.store(cutoff, Ordering::Relaxed);
+}
+
+/// This perform recursive butterfly arithmetic
+pub fn recursive_butterfly_arithmetic<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    n: usize,
+    twiddle_chunk: usize,
+    twiddles: &[Scalar],
+) {
+    if n == 2 {
+        let t = a[1];
+        a[1] = a[0];
+        a[0] += &t;
+        a[1] -= &t;
+    } else if n <= FFT_SERIAL_CUTOFF.load(Ordering::Relaxed)
+        && twiddle_chunk >= multicore::current_num_threads()
+    {
+        // There are `twiddle_chunk` sub-transforms of this size, which is
+        // enough to keep every thread busy without splitting further.
+        serial::butterfly_rounds(a, n.trailing_zeros(), twiddles, twiddle_chunk);
+    } else {
+        let (left, right) = a.split_at_mut(n / 2);
+        multicore::join(
+            || recursive_butterfly_arithmetic(left, n / 2, twiddle_chunk * 2, twiddles),
+            || recursive_butterfly_arithmetic(right, n / 2, twiddle_chunk * 2, twiddles),
+        );
+
+        // case when twiddle factor is one
+        let (a, left) = left.split_at_mut(1);
+        let (b, right) = right.split_at_mut(1);
+        let t = b[0];
+        b[0] = a[0];
+        a[0] += &t;
+        b[0] -= &t;
+
+        butterflies(left, right, twiddles, twiddle_chunk);
+    }
+}
+
+/// Applies a butterfly to each pair `(left[i], right[i])`, using the twiddle
+/// factor at index `(i + 1) * twiddle_chunk`.
+#[cfg(not(feature = "simd"))]
+fn butterflies<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    left: &mut [G],
+    right: &mut [G],
+    twiddles: &[Scalar],
+    twiddle_chunk: usize,
+) {
+    left.iter_mut()
+        .zip(right.iter_mut())
+        .enumerate()
+        .for_each(|(i, (a, b))| {
+            let mut t = *b;
+            t *= &twiddles[(i + 1) * twiddle_chunk];
+            *b = *a;
+            *a += &t;
+            *b -= &t;
+        });
+}
+
+/// Applies a butterfly to each pair `(left[i], right[i])`, using the twiddle
+/// factor at index `(i + 1) * twiddle_chunk`.
+///
+/// The pairs are processed in fixed-size lanes, which lets the compiler
+/// vectorize the field additions and subtractions.
+#[cfg(feature = "simd")]
+fn butterflies<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    left: &mut [G],
+    right: &mut [G],
+    twiddles: &[Scalar],
+    twiddle_chunk: usize,
+) {
+    const LANES: usize = 4;
+
+    let processed = left.len() - left.len() % LANES;
+    let mut left_lanes = left.chunks_exact_mut(LANES);
+    let mut right_lanes = right.chunks_exact_mut(LANES);
+    for (lane, (left, right)) in (&mut left_lanes).zip(&mut right_lanes).enumerate() {
+        let t: [G; LANES] = core::array::from_fn(|k| {
+            right[k] * &twiddles[(lane * LANES + k + 1) * twiddle_chunk]
+        });
+        for ((a, b), t) in left.iter_mut().zip(right.iter_mut()).zip(t.iter()) {
+            *b = *a;
+            *a += t;
+            *b -= t;
+        }
+    }
+
+    for (i, (a, b)) in left_lanes
+        .into_remainder()
+        .iter_mut()
+        .zip(right_lanes.into_remainder().iter_mut())
+        .enumerate()
+    {
+        let mut t = *b;
+        t *= &twiddles[(processed + i + 1) * twiddle_chunk];
+        *b = *a;
+        *a += &t;
+        *b -= &t;
+    }
+}
+
+/// Performs an inverse FFT on a vector of size $n = 2^k$, when provided
+/// `log_n` = $k$ and `omega_inv` = $\omega^{-1}$, where $\omega$ has
+/// multiplicative order $n$. This runs [`best_fft`] with $\omega^{-1}$ and
+/// then divides each resulting element by $n$, so that it undoes a call to
+/// `best_fft(a, omega, log_n)`.
+///
+/// This will use multithreading if beneficial.
+pub fn best_ifft<Scalar: PrimeField, G: FftGroup<Scalar>>(
+    a: &mut [G],
+    omega_inv: Scalar,
+    log_n: u32,
+) {
+    best_fft(a, omega_inv, log_n);
+    divide_by_n(a, log_n);
+}
+
+/// Returns the coefficients of the polynomial of degree less than $n = 2^k$
+/// that takes the values `evals` at $\omega^0, \dots, \omega^{n - 1}$, when
+/// provided `log_n` = $k$ and an `omega` = $\omega$ of multiplicative order
+/// $n$.
+///
+/// Interpolating over the subgroup generated by $\omega$ is exactly the
+/// inverse FFT, so this is [`best_ifft`] on a copy of `evals`, taking
+/// $O(n \log n)$ operations rather than the $O(n^2)$ of
+/// [`lagrange_interpolate`] for arbitrary points. It inverts `omega` itself,
+/// and only requires a [`Field`].
+///
+/// This function will panic if `evals` does not have $n$ elements, or if
+/// `omega` is zero.
+pub fn interpolate_over_subgroup<F: Field>(evals: &[F], omega: F, log_n: u32) -> Vec<F> {
+    assert_eq!(evals.len(), 1 << log_n);
+
+    let mut coeffs = evals.to_vec();
+    best_fft(&mut coeffs, omega.invert().unwrap(), log_n);
+    divide_by_n(&mut coeffs, log_n);
+    coeffs
+}
+
+/// Performs an FFT over the multiplicative coset $g H$, where $H$ is the
+/// subgroup of order $n = 2^k$ generated by `omega` and `log_n` = $k$. The
+/// vector `a`, when interpreted as the coefficients of a polynomial, is
+/// transformed into its evaluations at $g \omega^i$ for each $i$.
+///
+/// This will use multithreading if beneficial.
+pub fn coset_fft<Scalar: Field, G: FftGroup<Scalar>>(
+    a: &mut [G],
+    omega: Scalar,
+    g: Scalar,
+    log_n: u32,
+) {
+    distribute_powers(a, g);
+    best_fft(a, omega, log_n);
+}
+
+/// Inverts [`coset_fft`], when provided `omega_inv` = $\omega^{-1}$ and `g_inv`
+/// = $g^{-1}$.
+///
+/// This will use multithreading if beneficial.
+pub fn coset_ifft<Scalar: PrimeField, G: FftGroup<Scalar>>(
+    a: &mut [G],
+    omega_inv: Scalar,
+    g_inv: Scalar,
+    log_n: u32,
+) {
+    best_ifft(a, omega_inv, log_n);
+    distribute_powers(a, g_inv);
+}
+
+/// Multiplies `a[i]` by `g^i` for each `i`.
+fn distribute_powers<Scalar: Field, G: FftGroup<Scalar>>(a: &mut [G], g: Scalar) {
+    parallelize(a, |a, start| {
+        let g_start = g.pow_vartime([start as u64, 0, 0, 0]);
+        for (a, g_power) in a.iter_mut().zip(powers(g)) {
+            *a *= &(g_start * g_power);
+        }
+    });
+}
+
+/// Performs a Fast-Fourier Transformation (FFT) on a vector of arbitrary size
+/// $n$, given an element `omega` ($\omega$) of multiplicative order $n$. As with
+/// [`best_fft`], the vector `a` is transformed into the evaluations of the
+/// polynomial it represents at each of the $n$ distinct powers of $\omega$.
+///
+/// This uses Bluestein's algorithm, which expresses the transform as a
+/// convolution that is computed with radix-$2$ FFTs over a padded buffer of
+/// size at least $2n - 1$. Power-of-two sizes are passed to [`best_fft`]
+/// directly, which remains the fast path.
+///
+/// This function will panic if the padded size exceeds $2^S$.
+pub fn fft_arbitrary<Scalar: PrimeField, G: FftGroup<Scalar>>(a: &mut [G], omega: Scalar) {
+    let n = a.len();
+    if n <= 1 {
+        return;
+    }
+    if n.is_power_of_two() {
+        best_fft(a, omega, log2_floor(n));
+        return;
+    }
+
+    // Size of the padded convolution.
+    let m = (2 * n - 1).next_power_of_two();
+    let log_m = m.trailing_zeros();
+    assert!(log_m <= Scalar::S);
+    let omega_m = root_of_unity::<Scalar>(log_m);
+    let omega_m_inv = root_of_unity_inv::<Scalar>(log_m);
+
+    // Using jk = C(j + k, 2) - C(j, 2) - C(k, 2), the transform becomes
+    // A_k = w^{-C(k, 2)} * \sum_j (a_j w^{-C(j, 2)}) w^{C(j + k, 2)},
+    // where chirp[i] = w^{C(i, 2)} for i in 0..(2n - 1).
+    let mut chirp = Vec::with_capacity(2 * n - 1);
+    let mut cur = Scalar::ONE;
+    let mut w = Scalar::ONE;
+    for _ in 0..(2 * n - 1) {
+        chirp.push(cur);
+        cur *= w;
+        w *= omega;
+    }
+    let mut chirp_inv = chirp[..n].to_vec();
+    chirp_inv.iter_mut().batch_invert();
+
+    // The sum is a correlation, so reverse the first operand to make it a
+    // convolution.
+    let zero = a[0] * &Scalar::ZERO;
+    let mut u = vec![zero; m];
+    for (j, (a, chirp_inv)) in a.iter().zip(chirp_inv.iter()).enumerate() {
+        u[n - 1 - j] = *a * chirp_inv;
+    }
+    let mut v = vec![Scalar::ZERO; m];
+    v[..(2 * n - 1)].copy_from_slice(&chirp);
+
+    best_fft(&mut u, omega_m, log_m);
+    best_fft(&mut v, omega_m, log_m);
+    parallelize(&mut u, |u, start| {
+        for (u, v) in u.iter_mut().zip(v[start..].iter()) {
+            *u *= v;
+        }
+    });
+    best_ifft(&mut u, omega_m_inv, log_m);
+
+    for (k, (a, chirp_inv)) in a.iter_mut().zip(chirp_inv.iter()).enumerate() {
+        *a = u[n - 1 + k] * chirp_inv;
+    }
+}
+
+/// Multiplies two polynomials given in coefficient form, using FFTs over a
+/// domain of size $2^k$ where `log_n` = $k$ and `omega` has multiplicative
+/// order $2^k$.
+///
+/// The product has `a.len() + b.len() - 1` coefficients, so $2^k$ must be at
+/// least that large for the cyclic convolution not to wrap around; this
+/// function will panic otherwise.
+pub fn poly_mul<F: Field>(a: &[F], b: &[F], omega: F, log_n: u32) -> Vec<F> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let n = 1 << log_n;
+    let result_len = a.len() + b.len() - 1;
+    assert!(
+        n >= result_len,
+        "domain size ({}) must be at least a.len() + b.len() - 1 ({})",
+        n,
+        result_len
+    );
+
+    let mut product = a.to_vec();
+    convolve(&mut product, b, omega, omega.invert().unwrap(), log_n);
+    product.truncate(result_len);
+    product
+}
+
+/// Replaces `a` with the cyclic convolution of `a` and `b`, that is, with the
+/// coefficients of their product modulo $X^n - 1$, where $n = 2^k$ when
+/// provided `log_n` = $k$. `omega` must have multiplicative order $n$, and
+/// `omega_inv` must be its inverse.
+///
+/// `a` is padded with zeros to $n$ coefficients and transformed in place, and
+/// `b` is copied into a single padded buffer, so that only one allocation of
+/// $n$ elements is made. Terms of degree $n$ or more wrap around; for the
+/// ordinary product, $n$ must be at least `a.len() + b.len() - 1`, as in
+/// [`poly_mul`].
+///
+/// This function will panic if `a` or `b` has more than $n$ coefficients.
+pub fn convolve<F: Field>(a: &mut Vec<F>, b: &[F], omega: F, omega_inv: F, log_n: u32) {
+    let n = 1 << log_n;
+    assert!(
+        a.len() <= n && b.len() <= n,
+        "operands of length {} and {} do not fit in a domain of size {}",
+        a.len(),
+        b.len(),
+        n
+    );
+
+    a.resize(n, F::ZERO);
+    let mut b_evals = vec![F::ZERO; n];
+    b_evals[..b.len()].copy_from_slice(b);
+
+    best_fft(a, omega, log_n);
+    best_fft(&mut b_evals, omega, log_n);
+    parallelize(a, |a, start| {
+        for (a, b) in a.iter_mut().zip(b_evals[start..].iter()) {
+            *a *= b;
+        }
+    });
+
+    best_fft(a, omega_inv, log_n);
+    divide_by_n(a, log_n);
+}
+
+/// Below this operand length, [`poly_mul_toom3`] and [`poly_mul_auto`] fall
+/// back to schoolbook multiplication.
+const TOOM3_THRESHOLD: usize = 32;
+
+/// Above this product length, [`poly_mul_auto`] multiplies with FFTs rather
+/// than Toom-Cook-3.
+const POLY_MUL_FFT_THRESHOLD: usize = 1024;
+
+/// Multiplies two polynomials given in coefficient form by schoolbook
+/// multiplication, which takes $O(n m)$ field multiplications.
+pub fn poly_mul_schoolbook<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let mut result = vec![F::ZERO; a.len() + b.len() - 1];
+    for (i, a) in a.iter().enumerate() {
+        for (result, b) in result[i..].iter_mut().zip(b.iter()) {
+            *result += *a * b;
+        }
+    }
+    result
+}
+
+/// Multiplies two polynomials given in coefficient form with Toom-Cook-3.
+///
+/// Each operand is split into three limbs of $k$ coefficients, and viewed as a
+/// quadratic in $Y = X^k$. The two quadratics are evaluated at
+/// $0, 1, -1, 2, -2$, the evaluations are multiplied recursively, and the
+/// product is recovered with [`lagrange_interpolate`]. The field must
+/// therefore have characteristic greater than 3.
+pub fn poly_mul_toom3<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+    let two = F::ONE.double();
+    let points = [F::ZERO, F::ONE, -F::ONE, two, -two];
+    // The i-th row holds the coefficients of the i-th Lagrange basis polynomial,
+    // i.e. the weights of the i-th evaluation in each coefficient of the product.
+    let weights: Vec<Vec<F>> = (0..points.len())
+        .map(|i| {
+            let mut evals = [F::ZERO; 5];
+            evals[i] = F::ONE;
+            lagrange_interpolate(&points, &evals)
+        })
+        .collect();
+
+    toom3(a, b, &points, &weights)
+}
+
+fn toom3<F: Field>(a: &[F], b: &[F], points: &[F], weights: &[Vec<F>]) -> Vec<F> {
+    let (short, long) = (a.len().min(b.len()), a.len().max(b.len()));
+    // Toom-3 only pays off when both operands are long and of similar lengths.
+    if short < TOOM3_THRESHOLD || 3 * short <= long {
+        return poly_mul_schoolbook(a, b);
+    }
+
+    let k = (long + 2) / 3;
+    let evaluate = |poly: &[F], point: F| -> Vec<F> {
+        let limb = |i: usize, m: usize| poly.get(i * k + m).copied().unwrap_or(F::ZERO);
+        (0..k)
+            .map(|m| serial::eval_polynomial(&[limb(0, m), limb(1, m), limb(2, m)], point))
+            .collect()
+    };
+
+    let products: Vec<Vec<F>> = points
+        .iter()
+        .map(|point| toom3(&evaluate(a, *point), &evaluate(b, *point), points, weights))
+        .collect();
+
+    // Coefficients past the end of the product are zero, so truncating each
+    // contribution to them is harmless.
+    let mut result = vec![F::ZERO; a.len() + b.len() - 1];
+    for (product, weights) in products.iter().zip(weights.iter()) {
+        for (j, weight) in weights.iter().enumerate() {
+            for (result, coeff) in result.iter_mut().skip(j * k).zip(product.iter()) {
+                *result += *weight * coeff;
+            }
+        }
+    }
+    result
+}
+
+/// Multiplies two polynomials given in coefficient form, choosing between
+/// [`poly_mul_schoolbook`], [`poly_mul_toom3`] and the FFT-based [`poly_mul`]
+/// depending on their lengths.
+pub fn poly_mul_auto<F: PrimeField>(a: &[F], b: &[F]) -> Vec<F> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let result_len = a.len() + b.len() - 1;
+    let log_n = log2_ceil(result_len);
+    if a.len().min(b.len()) < TOOM3_THRESHOLD {
+        poly_mul_schoolbook(a, b)
+    } else if result_len <= POLY_MUL_FFT_THRESHOLD || log_n > F::S {
+        poly_mul_toom3(a, b)
+    } else {
+        let omega = root_of_unity(log_n);
+        poly_mul(a, b, omega, log_n)
+    }
+}
+
+/// Below this length, [`poly_add`], [`poly_sub`], [`poly_scale`] and
+/// [`poly_neg`] run on the current thread only.
+const POLY_PARALLEL_THRESHOLD: usize = 1 << 10;
+
+/// Returns the coefficients `coeff(i)` for `i` in `0..len`, computed in
+/// parallel if there are enough of them to benefit from it.
+fn poly_map<F: Field>(len: usize, coeff: impl Fn(usize) -> F + Send + Sync) -> Vec<F> {
+    let mut result = vec![F::ZERO; len];
+    let fill = |result: &mut [F], start: usize| {
+        for (i, result) in result.iter_mut().enumerate() {
+            *result = coeff(start + i);
+        }
+    };
+    if len < POLY_PARALLEL_THRESHOLD {
+        fill(&mut result, 0);
+    } else {
+        parallelize(&mut result, fill);
+    }
+    result
+}
+
+/// Adds two polynomials given in coefficient form. The shorter one is treated
+/// as padded with zeros, so the sum has as many coefficients as the longer.
+pub fn poly_add<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+    let coeff = |poly: &[F], i: usize| poly.get(i).copied().unwrap_or(F::ZERO);
+    poly_map(std::cmp::max(a.len(), b.len()), |i| coeff(a, i) + coeff(b, i))
+}
+
+/// Subtracts `b` from `a`, both given in coefficient form. The shorter one is
+/// treated as padded with zeros, so the difference has as many coefficients as
+/// the longer.
+pub fn poly_sub<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+    let coeff = |poly: &[F], i: usize| poly.get(i).copied().unwrap_or(F::ZERO);
+    poly_map(std::cmp::max(a.len(), b.len()), |i| coeff(a, i) - coeff(b, i))
+}
+
+/// Multiplies a polynomial given in coefficient form by the scalar `scale`.
+pub fn poly_scale<F: Field>(a: &[F], scale: F) -> Vec<F> {
+    poly_map(a.len(), |i| a[i] * scale)
+}
+
+/// Negates a polynomial given in coefficient form.
+pub fn poly_neg<F: Field>(a: &[F]) -> Vec<F> {
+    poly_map(a.len(), |i| -a[i])
+}
+
+/// Returns a primitive $2^k$-th root of unity $\omega$ when provided `log_n` =
+/// $k$, obtained by squaring `F::ROOT_OF_UNITY` $S - k$ times, where
+/// $2^S$ is the order of `F::ROOT_OF_UNITY`. This is the `omega` expected by
+/// [`best_fft`] for a domain of size $2^k$.
+///
+/// This function will panic if `log_n` exceeds `F::S`.
+pub fn root_of_unity<F: PrimeField>(log_n: u32) -> F {
+    assert!(log_n <= F::S, "there is no root of unity of order 2^{}", log_n);
+    (log_n..F::S).fold(F::ROOT_OF_UNITY, |omega, _| omega.square())
+}
+
+/// Returns $\omega^{-1}$, for the root of unity $\omega$ returned by
+/// [`root_of_unity`] for `log_n`, obtained by squaring
+/// `F::ROOT_OF_UNITY_INV` rather than inverting.
+///
+/// This function will panic if `log_n` exceeds `F::S`.
+pub fn root_of_unity_inv<F: PrimeField>(log_n: u32) -> F {
+    assert!(log_n <= F::S, "there is no root of unity of order 2^{}", log_n);
+    (log_n..F::S).fold(F::ROOT_OF_UNITY_INV, |omega_inv, _| omega_inv.square())
+}
+
+/// Panics with a descriptive message if a domain of size `2^k` cannot be
+/// built, either because the scalar field has no root of unity of that order
+/// or because `1 << k` would overflow `usize`.
+fn assert_lagrange_domain<C: CurveAffine>(k: u32) {
+    assert!(
+        k <= C::Scalar::S,
+        "g_to_lagrange: k = {} exceeds the two-adicity of the scalar field ({})",
+        k,
+        C::Scalar::S
+    );
+    assert!(
+        k < usize::BITS,
+        "g_to_lagrange: k = {} is too large, 1 << k overflows usize",
+        k
+    );
+}
+
+/// Convert coefficient bases group elements to lagrange basis by inverse FFT.
+///
+/// This function will panic if `k` exceeds the two-adicity of the scalar
+/// field, or if `1 << k` does not fit in a `usize`.
+pub fn g_to_lagrange<C: CurveAffine>(g_projective: Vec<C::Curve>, k: u32) -> Vec<C> {
+    assert_lagrange_domain::<C>(k);
+    let mut g_lagrange = vec![C::identity(); 1 << k];
+    g_to_lagrange_into(g_projective, k, &mut g_lagrange);
+    g_lagrange
+}
+
+/// Convert coefficient bases group elements to lagrange basis by inverse FFT,
+/// writing the result into `out` instead of allocating it.
+///
+/// This function will panic if `k` is out of range, as for [`g_to_lagrange`],
+/// or if `out` does not have `1 << k` elements.
+pub fn g_to_lagrange_into<C: CurveAffine>(g_projective: Vec<C::Curve>, k: u32, out: &mut [C]) {
+    assert_lagrange_domain::<C>(k);
+    assert_eq!(out.len(), 1 << k);
+
+    let omega_inv = root_of_unity_inv::<C::Scalar>(k);
+
+    let mut g_lagrange_projective = g_projective;
+    best_ifft(&mut g_lagrange_projective, omega_inv, k);
+
+    parallelize(out, |g_lagrange, starts| {
+        C::Curve::batch_normalize(
+            &g_lagrange_projective[starts..(starts + g_lagrange.len())],
+            g_lagrange,
+        );
+    });
+}
+
+/// The threshold set with [`set_eval_parallel_threshold`], or zero if unset.
+static EVAL_PARALLEL_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the number of coefficients (or roots) below which [`eval_polynomial`]
+/// and [`vanishing_poly_eval`] run on the current thread instead of splitting
+/// the work across the thread pool.
+///
+/// By default, or when set to zero, they run serially when there are fewer
+/// than half as many coefficients as threads.
+pub fn set_eval_parallel_threshold(threshold: usize) {
+    EVAL_PARALLEL_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Returns whether an evaluation over `n` coefficients or roots runs on the
+/// current thread; see [`set_eval_parallel_threshold`].
+fn eval_is_serial(n: usize) -> bool {
+    match EVAL_PARALLEL_THRESHOLD.load(Ordering::Relaxed) {
+        0 => n * 2 < multicore::current_num_threads(),
+        threshold => n < threshold,
+    }
+}
+
+/// This evaluates a provided polynomial (in coefficient form) at `point`.
+pub fn eval_polynomial<F: Field>(poly: &[F], point: F) -> F {
+    eval_polynomial_ref(poly, &point)
+}
+
+/// Like [`eval_polynomial`], but borrows `point`, for callers evaluating many
+/// polynomials at the same point.
+pub fn eval_polynomial_ref<F: Field>(poly: &[F], point: &F) -> F {
+    use serial::eval_polynomial as evaluate;
+
+    let n = poly.len();
+    if n <= 1 {
+        // Constant (or empty) polynomial
+        return poly.first().copied().unwrap_or(F::ZERO);
+    }
+
+    if eval_is_serial(n) {
+        evaluate(poly, *point)
+    } else {
+        parallelize_map(poly, |poly, start| {
+            evaluate(poly, *point) * point.pow_vartime([start as u64, 0, 0, 0])
+        })
+        .into_iter()
+        .fold(F::ZERO, |acc, part| acc + part)
+    }
+}
+
+/// This evaluates a provided polynomial (in coefficient form) at each of
+/// `points`, returning the evaluations in the same order as `points`.
+///
+/// The work is split across `points`, with each thread evaluating the whole
+/// polynomial. When there are fewer points than threads, each point is instead
+/// evaluated with the parallel [`eval_polynomial`].
+pub fn eval_polynomial_batch<F: Field>(poly: &[F], points: &[F]) -> Vec<F> {
+    let num_threads = multicore::current_num_threads();
+    if points.len() < num_threads {
+        return points
+            .iter()
+            .map(|point| eval_polynomial(poly, *point))
+            .collect();
+    }
+
+    let mut evals = vec![F::ZERO; points.len()];
+    parallelize(&mut evals, |evals, start| {
+        for (eval, point) in evals.iter_mut().zip(points[start..].iter()) {
+            *eval = eval_polynomial_iter(poly.iter().rev().copied(), *point);
+        }
+    });
+    evals
+}
+
+/// This evaluates a provided polynomial (in coefficient form) at each of
+/// `points`, which need not lie in a multiplicative subgroup, returning the
+/// evaluations in the same order as `points`.
+///
+/// The points are arranged into a subproduct tree, whose nodes are the
+/// products $\prod_i (X - x_i)$ over ranges of points, and the polynomial is
+/// reduced modulo each node on the way down to the leaves, where the remainder
+/// modulo $X - x_i$ is $p(x_i)$. Since a [`Field`] has no roots of unity to
+/// multiply with [`poly_mul`], the tree is built with [`poly_mul_toom3`], and
+/// the remainders are computed from power series inverses using the same
+/// multiplication. For $n$ points and a polynomial of degree $O(n)$, this takes
+/// $O(n^{1.47} \log n)$ field operations, rather than the $O(n^2)$ of calling
+/// [`eval_polynomial`] per point, and pays off for a few hundred points or
+/// more. Smaller point sets are evaluated with [`eval_polynomial_batch`].
+pub fn multipoint_eval<F: Field>(poly: &[F], points: &[F]) -> Vec<F> {
+    if points.len() < TOOM3_THRESHOLD {
+        return eval_polynomial_batch(poly, points);
+    }
+
+    // tree[0] holds the leaves X - x_i, and each level above holds the products
+    // of adjacent pairs of the level below, the last node of an odd level being
+    // carried up unchanged.
+    let mut tree: Vec<Vec<Vec<F>>> = vec![points.iter().map(|x| vec![-*x, F::ONE]).collect()];
+    while tree.last().unwrap().len() > 1 {
+        let level = tree.last().unwrap();
+        let next = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => poly_mul_toom3(a, b),
+                [a] => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        tree.push(next);
+    }
+
+    let mut remainders = vec![poly_rem_monic(poly, &tree.last().unwrap()[0])];
+    for level in tree.iter().rev().skip(1) {
+        remainders = level
+            .iter()
+            .enumerate()
+            .map(|(i, node)| poly_rem_monic(&remainders[i / 2], node))
+            .collect();
+    }
+    remainders
+        .into_iter()
+        .map(|r| r.first().copied().unwrap_or(F::ZERO))
+        .collect()
+}
+
+/// Returns the remainder of the division of `a` by the monic polynomial `b`,
+/// with `b.len() - 1` coefficients.
+fn poly_rem_monic<F: Field>(a: &[F], b: &[F]) -> Vec<F> {
+    let m = b.len() - 1;
+    if a.len() <= m {
+        return a.to_vec();
+    }
+
+    let k = a.len() - m;
+    if k.min(m) < TOOM3_THRESHOLD {
+        let mut r = a.to_vec();
+        for i in (m..r.len()).rev() {
+            let lead = r[i];
+            for (r, b) in r[i - m..i].iter_mut().zip(b.iter()) {
+                *r -= lead * b;
+            }
+        }
+        r.truncate(m);
+        return r;
+    }
+
+    // Reversing the coefficients of a = bq + r turns the quotient into the
+    // first k coefficients of rev(a) / rev(b), as a power series in X.
+    let a_rev: Vec<F> = a.iter().rev().take(k).copied().collect();
+    let b_rev: Vec<F> = b.iter().rev().copied().collect();
+    let mut q = poly_mul_toom3(&a_rev, &power_series_inverse(&b_rev, k));
+    q.truncate(k);
+    q.reverse();
+
+    let bq = poly_mul_toom3(b, &q);
+    poly_sub(&a[..m], &bq[..m])
+}
+
+/// Returns the first `n` coefficients of the power series $1 / f$ by Newton
+/// iteration. `f[0]` must be nonzero.
+fn power_series_inverse<F: Field>(f: &[F], n: usize) -> Vec<F> {
+    let mut g = vec![f[0].invert().unwrap()];
+    while g.len() < n {
+        // g <- g (2 - f g), which doubles the number of correct coefficients.
+        let len = std::cmp::min(2 * g.len(), n);
+        let mut e = poly_mul_toom3(&f[..std::cmp::min(len, f.len())], &g);
+        e.resize(len, F::ZERO);
+        let mut e = poly_neg(&e);
+        e[0] += F::ONE.double();
+        g = poly_mul_toom3(&g, &e);
+        g.resize(len, F::ZERO);
+    }
+    g
+}
+
+/// This evaluates the random linear combination $\sum_i r^i p_i(z)$ of
+/// `polys` (in coefficient form, of possibly different lengths) at `z`. Returns
+/// zero if `polys` is empty.
+///
+/// The work is split across `polys`, with each thread evaluating whole
+/// polynomials. When there are fewer polynomials than threads, each one is
+/// instead evaluated with the parallel [`eval_polynomial`].
+pub fn eval_polynomial_rlc<F: Field>(polys: &[&[F]], r: F, z: F) -> F {
+    let combine = |polys: &[&[F]], eval: &dyn Fn(&[F]) -> F| {
+        polys
+            .iter()
+            .zip(powers(r))
+            .fold(F::ZERO, |acc, (poly, r_i)| acc + eval(poly) * r_i)
+    };
+
+    if polys.len() < multicore::current_num_threads() {
+        return combine(polys, &|poly| eval_polynomial(poly, z));
+    }
+
+    parallelize_map(polys, |polys, start| {
+        combine(polys, &|poly| serial::eval_polynomial(poly, z)) * r.pow_vartime([start as u64])
+    })
+    .into_iter()
+    .fold(F::ZERO, |acc, part| acc + part)
+}
+
+/// This evaluates a polynomial at `point` using Horner's method, where the
+/// coefficients are yielded by `coeffs` starting from the highest degree, e.g.
+/// `poly.iter().rev().copied()` for a polynomial stored in coefficient form.
+///
+/// Unlike [`eval_polynomial`], this never collects the coefficients, and is
+/// always single-threaded.
+pub fn eval_polynomial_iter<F: Field, I: Iterator<Item = F>>(coeffs: I, point: F) -> F {
+    coeffs.fold(F::ZERO, |acc, coeff| acc * point + coeff)
+}
+
+/// This evaluates at `z` the polynomial of degree less than $n$ whose
+/// evaluations at $\omega^0, \dots, \omega^{n - 1}$ are `evals`, where `omega`
+/// $= \omega$ has multiplicative order $n$ = `evals.len()`.
+///
+/// Rather than interpolating the coefficients with an inverse FFT, this uses
+/// the barycentric formula
+/// $$\frac{z^n - 1}{n} \sum_i \frac{\text{evals}_i \, \omega^i}{z - \omega^i}.$$
+/// If `z` lies in the domain, the matching entry of `evals` is returned.
+pub fn eval_lagrange<F: Field>(evals: &[F], omega: F, z: F) -> F {
+    let n = evals.len();
+    if n == 0 {
+        return F::ZERO;
+    }
+
+    let omega_powers = powers_bounded(omega, n);
+    let vanishing = z.pow_vartime([n as u64]) - F::ONE;
+    if vanishing.is_zero_vartime() {
+        if let Some(i) = omega_powers.iter().position(|omega_i| *omega_i == z) {
+            return evals[i];
+        }
+    }
+
+    let mut terms: Vec<F> = omega_powers.iter().map(|omega_i| z - omega_i).collect();
+    parallel_batch_invert(&mut terms);
+    parallelize(&mut terms, |terms, start| {
+        for ((term, eval), omega_i) in terms
+            .iter_mut()
+            .zip(evals[start..].iter())
+            .zip(omega_powers[start..].iter())
+        {
+            *term *= *eval * omega_i;
+        }
+    });
+
+    // Field only offers ONE, so n is built up by repeated addition.
+    let n_inv = evals
+        .iter()
+        .fold(F::ZERO, |acc, _| acc + F::ONE)
+        .invert()
+        .unwrap();
+    terms.iter().fold(F::ZERO, |acc, term| acc + term) * vanishing * n_inv
+}
+
+/// This evaluates at `z` the polynomial of degree less than $n$ that takes the
+/// values `evals` at the distinct `points` $x_0, \dots, x_{n - 1}$, as
+/// [`lagrange_interpolate`] followed by [`eval_polynomial`] would.
+///
+/// Rather than building the coefficients, this uses the barycentric formula
+/// $$\ell(z) \sum_j \frac{w_j \, \text{evals}_j}{z - x_j}, \quad
+/// \ell(z) = \prod_j (z - x_j), \quad w_j = \prod_{k \neq j} (x_j - x_k)^{-1},$$
+/// whose denominators are inverted together with a single batch inversion. The
+/// weights still take $O(n^2)$ multiplications for arbitrary points, but no
+/// polynomial products are formed. If `z` is one of `points`, the matching
+/// entry of `evals` is returned.
+///
+/// This function will panic if `points` and `evals` have a different length,
+/// or if `points` contains duplicates.
+pub fn lagrange_eval_at<F: Field>(points: &[F], evals: &[F], z: F) -> F {
+    assert_eq!(points.len(), evals.len());
+    if let Some(i) = points.iter().position(|x| *x == z) {
+        return evals[i];
+    }
+
+    // (z - x_j) * prod_{k != j} (x_j - x_k), for each j
+    let mut denoms = vec![F::ZERO; points.len()];
+    parallelize(&mut denoms, |denoms, start| {
+        for (j, denom) in (start..).zip(denoms.iter_mut()) {
+            let x_j = points[j];
+            *denom = points
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != j)
+                .fold(z - x_j, |acc, (_, x_k)| acc * (x_j - x_k));
+        }
+    });
+    assert!(
+        denoms.iter().all(|denom| !denom.is_zero_vartime()),
+        "lagrange_eval_at: duplicate x-coordinates"
+    );
+    parallel_batch_invert(&mut denoms);
+
+    let l_z = points.iter().fold(F::ONE, |acc, x_j| acc * (z - x_j));
+    compute_inner_product(&denoms, evals) * l_z
+}
+
+/// This computes the inner product of two vectors `a` and `b`.
+///
+/// This function will panic if the two vectors are not the same size.
+///
+/// This will use multithreading if beneficial.
+pub fn compute_inner_product<F: Field>(a: &[F], b: &[F]) -> F {
+    use serial::compute_inner_product as inner_product;
+
+    assert_eq!(a.len(), b.len());
+
+    let n = a.len();
+    let num_threads = multicore::current_num_threads();
+    if n < num_threads {
+        inner_product(a, b)
+    } else {
+        let chunk_size = (n + num_threads - 1) / num_threads;
+        let mut parts = vec![F::ZERO; num_threads];
+        multicore::scope(|scope| {
+            for (out, (a, b)) in parts
+                .chunks_mut(1)
+                .zip(a.chunks(chunk_size).zip(b.chunks(chunk_size)))
+            {
+                scope.spawn(move |_| out[0] = inner_product(a, b));
+            }
+        });
+        parts.iter().fold(F::ZERO, |acc, part| acc + part)
+    }
+}
+
+/// Computes the same inner product as [`compute_inner_product`], serially,
+/// with Kahan's compensated summation.
+///
+/// This only makes a difference for inexact fields, such as mock fields backed
+/// by floating-point numbers in tests, in which the rounding error of the sum
+/// no longer grows with the length of the vectors. In a prime field the
+/// compensation term is always zero, and the result is that of
+/// [`compute_inner_product`].
+///
+/// This function will panic if the two vectors are not the same size.
+pub fn compute_inner_product_compensated<F: Field>(a: &[F], b: &[F]) -> F {
+    assert_eq!(a.len(), b.len());
+
+    let mut sum = F::ZERO;
+    // The part of the products added so far that is missing from `sum`,
+    // negated.
+    let mut compensation = F::ZERO;
+    for (a, b) in a.iter().zip(b.iter()) {
+        let y = *a * b - compensation;
+        let t = sum + y;
+        compensation = (t - sum) - y;
+        sum = t;
+    }
+    sum
+}
+
+/// Computes the inner product $\langle a, G \rangle = \sum_i a_i G_i$ of
+/// `scalars` with the group elements `bases`.
+///
+/// This is the multi-exponentiation of [`best_multiexp_cpu`], named for use in
+/// inner product arguments.
+///
+/// This function will panic if the two vectors are not the same size.
+///
+/// This will use multithreading if beneficial.
+pub fn inner_product_msm<C: CurveAffine>(scalars: &[C::Scalar], bases: &[C]) -> C::Curve {
+    assert_eq!(scalars.len(), bases.len());
+
+    best_multiexp_cpu(scalars, bases)
+}
+
+/// Divides polynomial `a` in `X` by `X - b`, checking that there is no
+/// remainder.
+///
+/// Returns the quotient if `X - b` divides `a` exactly, and otherwise returns
+/// the nonzero remainder (which is the evaluation of `a` at `b`) as the error.
+pub fn kate_division_checked<'a, F: Field, I: IntoIterator<Item = &'a F>>(
+    a: I,
+    mut b: F,
+) -> Result<Vec<F>, F>
+where
+    I::IntoIter: DoubleEndedIterator + ExactSizeIterator,
+{
+    b = -b;
+    let mut a = a.into_iter();
+    let constant_coeff = a.next();
+
+    let mut q = vec![F::ZERO; a.len()];
+
+    let mut tmp = F::ZERO;
+    for (q, r) in q.iter_mut().rev().zip(a.rev()) {
+        let mut lead_coeff = *r;
+        lead_coeff.sub_assign(&tmp);
+        *q = lead_coeff;
+        tmp = lead_coeff;
+        tmp.mul_assign(&b);
+    }
+
+    let remainder = constant_coeff.map_or(F::ZERO, |coeff| *coeff - tmp);
+    if remainder.is_zero_vartime() {
+        Ok(q)
+    } else {
+        Err(remainder)
+    }
+}
+
+/// Divides polynomial `a` in `X` by each of `X - b_i` for `b_i` in `roots` with
+/// no remainder, like [`kate_division`], returning the quotients in the same
+/// order as `roots`.
+///
+/// The divisions are split across threads by root, all sharing `a`.
+pub fn kate_division_multi<F: Field>(a: &[F], roots: &[F]) -> Vec<Vec<F>> {
+    let mut quotients = vec![vec![]; roots.len()];
+    parallelize(&mut quotients, |quotients, start| {
+        for (q, root) in quotients.iter_mut().zip(roots[start..].iter()) {
+            *q = kate_division(a, *root);
+        }
+    });
+    quotients
+}
+
+/// Divides polynomial `a` in `X` (in coefficient form) by the vanishing
+/// polynomial $X^n - 1$ of the subgroup of order `n`.
+///
+/// In debug builds this panics if $X^n - 1$ does not divide `a` exactly. It
+/// also panics if `n` is zero.
+pub fn divide_by_vanishing_poly<F: Field>(a: &[F], n: usize) -> Vec<F> {
+    assert!(n > 0);
+    if a.len() <= n {
+        debug_assert!(
+            a.iter().all(|coeff| coeff.is_zero_vartime()),
+            "polynomial is not divisible by X^n - 1"
+        );
+        return vec![];
+    }
+
+    // Since X^n = 1 modulo X^n - 1, each leading coefficient is both a
+    // quotient coefficient and added back n positions lower.
+    let mut remainder = a.to_vec();
+    let mut q = vec![F::ZERO; a.len() - n];
+    for i in (n..a.len()).rev() {
+        let lead_coeff = remainder[i];
+        q[i - n] = lead_coeff;
+        remainder[i - n] += lead_coeff;
+    }
+    debug_assert!(
+        remainder[..n].iter().all(|coeff| coeff.is_zero_vartime()),
+        "polynomial is not divisible by X^n - 1"
+    );
+
+    q
+}
+
+/// This utility function will parallelize an operation that is to be
+/// performed over a mutable slice.
+pub fn parallelize<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(v: &mut [T], f: F) {
+    parallelize_with_threads(v, multicore::current_num_threads(), f);
+}
+
+/// Like [`parallelize`], but also passes `f` a [`ChaChaRng`] for its chunk,
+/// seeded with `seed` XORed with the chunk offset (as little-endian bytes), so
+/// that chunks can sample randomness without sharing a locked RNG.
+///
+/// The output is reproducible for a given `seed` and number of threads. As the
+/// chunks depend on the number of threads, it differs across thread counts.
+pub fn parallelize_with_rng<T, F>(v: &mut [T], seed: <ChaChaRng as SeedableRng>::Seed, f: F)
+where
+    T: Send,
+    F: Fn(&mut [T], usize, &mut ChaChaRng) + Send + Sync + Clone,
+{
+    parallelize(v, |chunk, start| {
+        let mut chunk_seed = seed;
+        for (seed, offset) in chunk_seed.iter_mut().zip((start as u64).to_le_bytes()) {
+            *seed ^= offset;
+        }
+        f(chunk, start, &mut ChaChaRng::from_seed(chunk_seed))
+    });
+}
+
+/// Like [`parallelize`], but calls `on_chunk` with the offset of each chunk
+/// once `f` has finished processing it, e.g. to report progress. `on_chunk` is
+/// called concurrently from the worker threads.
+pub fn parallelize_with_progress<T, F, P>(v: &mut [T], f: F, on_chunk: P)
+where
+    T: Send,
+    F: Fn(&mut [T], usize) + Send + Sync + Clone,
+    P: Fn(usize) + Send + Sync,
+{
+    let on_chunk = &on_chunk;
+    parallelize(v, move |chunk, offset| {
+        f(chunk, offset);
+        on_chunk(offset);
+    });
+}
+
+/// Like [`parallelize`], but splits the slice into chunks for `threads`
+/// threads rather than for the size of the global thread pool. This is useful
+/// to cap parallelism inside an already-parallel region. A `threads` value of
+/// zero is treated as one.
+pub fn parallelize_with_threads<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(
+    v: &mut [T],
+    threads: usize,
+    f: F,
+) {
+    // Algorithm rationale:
+    //
+    // Using the stdlib `chunks_mut` will lead to severe load imbalance.
+    // From https://github.com/rust-lang/rust/blob/e94bda3/library/core/src/slice/iter.rs#L1607-L1637
+    // if the division is not exact, the last chunk will be the remainder.
+    //
+    // Dividing 40 items on 12 threads will lead to a chunk size of 40/12 = 3,
+    // There will be a 13 chunks of size 3 and 1 of size 1 distributed on 12 threads.
+    // This leads to 1 thread working on 6 iterations, 1 on 4 iterations and 10 on 3 iterations,
+    // a load imbalance of 2x.
+    //
+    // Instead we can divide work into chunks of size
+    // 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3 = 4*4 + 3*8 = 40
+    //
+    // This would lead to a 6/4 = 1.5x speedup compared to naive chunks_mut
+    //
+    // See also OpenMP spec (page 60)
+    // http://www.openmp.org/mp-documents/openmp-4.5.pdf
+    // "When no chunk_size is specified, the iteration space is divided into chunks
+    // that are approximately equal in size, and at most one chunk is distributed to
+    // each thread. The size of the chunks is unspecified in this case."
+    // This implies chunks are the same size ±1
+
+    let f = &f;
+    let total_iters = v.len();
+    let num_threads = std::cmp::max(threads, 1);
+    let base_chunk_size = total_iters / num_threads;
+    let cutoff_chunk_id = total_iters % num_threads;
+    let split_pos = cutoff_chunk_id * (base_chunk_size + 1);
+    let (v_hi, v_lo) = v.split_at_mut(split_pos);
+
+    multicore::scope(|scope| {
+        // Skip special-case: number of iterations is cleanly divided by number of threads.
+        if cutoff_chunk_id != 0 {
+            for (chunk_id, chunk) in v_hi.chunks_exact_mut(base_chunk_size + 1).enumerate() {
+                let offset = chunk_id * (base_chunk_size + 1);
+                scope.spawn(move |_| f(chunk, offset));
+            }
+        }
+        // Skip special-case: less iterations than number of threads.
+        if base_chunk_size != 0 {
+            for (chunk_id, chunk) in v_lo.chunks_exact_mut(base_chunk_size).enumerate() {
+                let offset = split_pos + (chunk_id * base_chunk_size);
+                scope.spawn(move |_| f(chunk, offset));
+            }
+        }
+    });
+}
+
+/// Like [`parallelize`], but over an immutable slice, with each call to `f`
+/// producing a value. The slice is split into the same load-balanced chunks
+/// as [`parallelize`], and the results are returned in chunk order, so that
+/// the first result is for the chunk at offset zero.
+pub fn parallelize_map<T: Sync, R: Send, F: Fn(&[T], usize) -> R + Sync>(
+    v: &[T],
+    f: F,
+) -> Vec<R> {
+    // See `parallelize_with_threads` for the choice of chunk sizes.
+    let f = &f;
+    let total_iters = v.len();
+    let num_threads = multicore::current_num_threads();
+    let base_chunk_size = total_iters / num_threads;
+    let cutoff_chunk_id = total_iters % num_threads;
+    let split_pos = cutoff_chunk_id * (base_chunk_size + 1);
+    let (v_hi, v_lo) = v.split_at(split_pos);
+
+    // When there are fewer iterations than threads, `v_lo` is empty.
+    let chunks: Vec<(usize, &[T])> = v_hi
+        .chunks_exact(base_chunk_size + 1)
+        .chain(v_lo.chunks_exact(std::cmp::max(base_chunk_size, 1)))
+        .scan(0, |offset, chunk| {
+            let start = *offset;
+            *offset += chunk.len();
+            Some((start, chunk))
+        })
+        .collect();
+
+    let mut results: Vec<Option<R>> = chunks.iter().map(|_| None).collect();
+    multicore::scope(|scope| {
+        for ((offset, chunk), out) in chunks.iter().zip(results.iter_mut()) {
+            scope.spawn(move |_| *out = Some(f(chunk, *offset)));
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.expect("every chunk has been processed"))
+        .collect()
+}
+
+/// Like [`parallelize`], but on machines with several NUMA nodes, splits `v`
+/// into one contiguous region per node, sized by its number of CPUs, and
+/// processes each region on threads pinned to the CPUs of that node.
+///
+/// Each region is always processed by the same node. Pages are placed on the
+/// node of the thread that first touches them, and the kernel's automatic NUMA
+/// balancing migrates them towards the node that keeps accessing them, so
+/// repeated passes over a buffer end up reading local memory rather than
+/// memory across sockets. The pinned pools are separate from the global pool,
+/// and are built on first use.
+///
+/// Without the `numa` feature, on single-node machines, or where the topology
+/// cannot be read (it is read from sysfs, so only on Linux), this is the same
+/// as [`parallelize`].
+pub fn parallelize_numa<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(v: &mut [T], f: F) {
+    #[cfg(feature = "numa")]
+    if let Some(nodes) = numa::node_pools() {
+        return numa::parallelize_on_nodes(&nodes, v, f);
+    }
+
+    parallelize(v, f)
+}
+
+/// Runs [`parallelize`] on the threads of `pool` instead of the global pool.
+pub fn parallelize_in<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(
+    pool: &ThreadPool,
+    v: &mut [T],
+    f: F,
+) {
+    pool.install(|| parallelize(v, f))
+}
+
+/// Runs [`best_fft`] on the threads of `pool` instead of the global pool.
+pub fn best_fft_in<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    pool: &ThreadPool,
+    a: &mut [G],
+    omega: Scalar,
+    log_n: u32,
+) {
+    pool.install(|| best_fft(a, omega, log_n))
+}
+
+/// Runs [`best_multiexp_cpu`] on the threads of `pool` instead of the global
+/// pool.
+pub fn best_multiexp_cpu_in<C: CurveAffine>(
+    pool: &ThreadPool,
+    coeffs: &[C::Scalar],
+    bases: &[C],
+) -> C::Curve {
+    pool.install(|| best_multiexp_cpu(coeffs, bases))
+}
+
+/// Runs [`eval_polynomial`] on the threads of `pool` instead of the global
+/// pool.
+pub fn eval_polynomial_in<F: Field>(pool: &ThreadPool, poly: &[F], point: F) -> F {
+    pool.install(|| eval_polynomial(poly, point))
+}
+
+/// Runs [`compute_inner_product`] on the threads of `pool` instead of the
+/// global pool.
+pub fn compute_inner_product_in<F: Field>(pool: &ThreadPool, a: &[F], b: &[F]) -> F {
+    pool.install(|| compute_inner_product(a, b))
+}
+
+/// Inverts every element of `v` in place, using Montgomery's trick on each
+/// thread's chunk of the slice.
+///
+/// As with [`BatchInvert`], zero elements have no inverse and are left as zero.
+pub fn parallel_batch_invert<F: Field>(v: &mut [F]) {
+    parallelize(v, |v, _| {
+        v.iter_mut().batch_invert();
+    });
+}
+
+/// Returns the base-2 logarithm of `num`, rounded down.
+///
+/// This function will panic if `num` is zero.
+pub fn log2_floor(num: usize) -> u32 {
+    assert!(num > 0);
+
+    usize::BITS - 1 - num.leading_zeros()
+}
+
+/// Returns the base-2 logarithm of `num`, rounded up.
+///
+/// This function will panic if `num` is zero.
+pub fn log2_ceil(num: usize) -> u32 {
+    if num.is_power_of_two() {
+        log2_floor(num)
+    } else {
+        log2_floor(num) + 1
+    }
+}
+
+/// Returns coefficients of an n - 1 degree polynomial given a set of n points
+/// and their evaluations, like [`lagrange_interpolate`].
+///
+/// Rather than expanding every Lagrange basis polynomial from its roots, this
+/// builds the master polynomial $\prod_j (X - x_j)$ once and obtains each basis
+/// polynomial from it with [`kate_division`], which is considerably faster for
+/// large point sets.
+///
+/// This function will panic if two values in `points` are the same, naming the
+/// indices of the first such pair.
+pub fn lagrange_interpolate_fast<F: Field>(points: &[F], evals: &[F]) -> Vec<F> {
+    assert_eq!(points.len(), evals.len());
+    if points.is_empty() {
+        return vec![];
+    }
+
+    let master = vanishing_poly_coeffs(points);
+
+    // Compute \prod_{k != j} (x_j - x_k)^(-1) for each j
+    let mut denoms: Vec<F> = points
+        .iter()
+        .enumerate()
+        .map(|(j, x_j)| {
+            points
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != j)
+                .fold(F::ONE, |acc, (_, x_k)| acc * (*x_j - x_k))
+        })
+        .collect();
+    // A repeated point shows up as a zero denominator.
+    if denoms.iter().any(|denom| bool::from(denom.is_zero())) {
+        serial::duplicate_point_panic("lagrange_interpolate_fast", points);
+    }
+    denoms.iter_mut().batch_invert();
+
+    let mut final_poly = vec![F::ZERO; points.len()];
+    for ((x_j, denom), eval) in points.iter().zip(denoms.into_iter()).zip(evals.iter()) {
+        let weight = denom * eval;
+        let basis = kate_division(&master, *x_j);
+        for (final_coeff, basis_coeff) in final_poly.iter_mut().zip(basis.into_iter()) {
+            *final_coeff += basis_coeff * weight;
+        }
+    }
+    final_poly
+}
+
+/// Incrementally interpolates a polynomial from `(x, y)` points in Newton
+/// form, so that points can be added one at a time without recomputing the
+/// interpolant from scratch.
+///
+/// The interpolant is kept as its Newton coefficients
+/// $f[x_0], f[x_0, x_1], \dots$, together with the divided differences ending
+/// at the latest point, which are all that is needed to add another point.
+#[derive(Clone, Debug, Default)]
+pub struct NewtonInterpolator<F: Field> {
+    points: Vec<F>,
+    coeffs: Vec<F>,
+    diagonal: Vec<F>,
+}
+
+impl<F: Field> NewtonInterpolator<F> {
+    /// Creates an interpolator with no points.
+    pub fn new() -> Self {
+        NewtonInterpolator {
+            points: vec![],
+            coeffs: vec![],
+            diagonal: vec![],
+        }
+    }
+
+    /// Adds the point `(x, y)` to the interpolant, in $O(n)$ field operations
+    /// and a single inversion for an interpolant of $n$ points.
+    ///
+    /// This function will panic if `x` has already been added.
+    pub fn add_point(&mut self, x: F, y: F) {
+        // denoms[k - 1] = x - x_{n - k}
+        let mut denoms: Vec<F> = self.points.iter().rev().map(|x_j| x - x_j).collect();
+        if let Some(k) = denoms.iter().position(|denom| denom.is_zero_vartime()) {
+            panic!(
+                "NewtonInterpolator: duplicate x-coordinate at index {}",
+                self.points.len() - 1 - k
+            );
+        }
+        denoms.iter_mut().batch_invert();
+
+        // diagonal[k] = f[x_{n - k}, ..., x_n]
+        let mut diagonal = Vec::with_capacity(self.diagonal.len() + 1);
+        diagonal.push(y);
+        for (k, (prev, denom)) in self.diagonal.iter().zip(denoms.iter()).enumerate() {
+            let next = (diagonal[k] - prev) * denom;
+            diagonal.push(next);
+        }
+
+        self.coeffs.push(*diagonal.last().unwrap());
+        self.diagonal = diagonal;
+        self.points.push(x);
+    }
+
+    /// Returns the coefficients of the polynomial of degree less than $n$ that
+    /// passes through the $n$ points added so far.
+    pub fn to_coeffs(&self) -> Vec<F> {
+        let mut poly: Vec<F> = Vec::with_capacity(self.coeffs.len());
+        // Horner's rule on the Newton form: p = p * (X - x_k) + c_k
+        for (c_k, x_k) in self.coeffs.iter().zip(self.points.iter()).rev() {
+            poly.push(F::ZERO);
+            for i in (1..poly.len()).rev() {
+                poly[i] = poly[i - 1] - poly[i] * x_k;
+            }
+            poly[0] = *c_k - poly[0] * x_k;
+        }
+        poly
+    }
+}
+
+/// This evaluates the vanishing polynomial $\prod_i (X - r_i)$ of `roots` at
+/// `z`.
+///
+/// The product is split across the thread pool, unless there are fewer than
+/// half as many roots as threads, in which case it is computed serially.
+pub fn vanishing_poly_eval<F: Field>(roots: &[F], z: F) -> F {
+    evaluate_vanishing_polynomial(roots, z)
+}
+
+/// Returns the coefficients of the vanishing polynomial $\prod_i (X - r_i)$ of
+/// `roots`, which has `roots.len() + 1` coefficients and a leading coefficient
+/// of one. Returns the constant polynomial $1$ if `roots` is empty.
+///
+/// The linear factors are multiplied in one at a time, which takes $O(n^2)$
+/// field operations for $n$ roots. This is fine up to a few hundred roots.
+pub fn vanishing_poly_coeffs<F: Field>(roots: &[F]) -> Vec<F> {
+    let mut coeffs = Vec::with_capacity(roots.len() + 1);
+    coeffs.push(F::ONE);
+    for root in roots {
+        coeffs.push(F::ZERO);
+        for i in (1..coeffs.len()).rev() {
+            coeffs[i] = coeffs[i - 1] - coeffs[i] * root;
+        }
+        coeffs[0] = -coeffs[0] * root;
+    }
+    coeffs
+}
+
+pub(crate) fn evaluate_vanishing_polynomial<F: Field>(roots: &[F], z: F) -> F {
+    fn evaluate<F: Field>(roots: &[F], z: F) -> F {
+        roots.iter().fold(F::ONE, |acc, point| (z - point) * acc)
+    }
+    if eval_is_serial(roots.len()) {
+        evaluate(roots, z)
+    } else {
+        parallelize_map(roots, |roots, _| evaluate(roots, z))
+            .into_iter()
+            .fold(F::ONE, |acc, part| acc * part)
+    }
+}
+
+/// Returns an infinite iterator over the powers $1, b, b^2, \dots$ of `base`
+/// $= b$.
+///
+/// When `base` is zero, this yields $1$ followed by zeros, as $0^0 = 1$.
+pub fn powers<F: Field>(base: F) -> impl Iterator<Item = F> {
+    std::iter::successors(Some(F::ONE), move |power| Some(base * power))
+}
+
+/// Returns the first `n` powers $1, b, \dots, b^{n - 1}$ of `base` $= b$, as
+/// yielded by `powers(base).take(n)`.
+///
+/// The powers are computed in parallel, with each thread starting its chunk
+/// from $b^{start}$. As with [`powers`], a zero `base` gives $1$ followed by
+/// zeros.
+pub fn powers_bounded<F: Field>(base: F, n: usize) -> Vec<F> {
+    let mut powers = vec![F::ZERO; n];
+    parallelize(&mut powers, |powers, start| {
+        let mut power = base.pow_vartime([start as u64]);
+        for p in powers.iter_mut() {
+            *p = power;
+            power *= base;
+        }
+    });
+    powers
+}
+
+#[cfg(test)]
+use rand_core::OsRng;
+
+#[cfg(test)]
+use crate::halo2curves::pasta::{Eq, EqAffine, Fp};
+
+#[test]
+fn test_lagrange_interpolate() {
+    let rng = OsRng;
+
+    let points = (0..5).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+    let evals = (0..5).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+
+    for coeffs in 0..5 {
+        let points = &points[0..coeffs];
+        let evals = &evals[0..coeffs];
+
+        let poly = lagrange_interpolate(points, evals);
+        assert_eq!(poly.len(), points.len());
+
+        for (point, eval) in points.iter().zip(evals) {
+            assert_eq!(eval_polynomial(&poly, *point), *eval);
+            assert_eq!(
+                eval_polynomial_iter(poly.iter().rev().copied(), *point),
+                *eval
+            );
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "lagrange_interpolate: duplicate x-coordinate at indices 1,3")]
+fn test_lagrange_interpolate_duplicate_points() {
+    let rng = OsRng;
+
+    let mut points = (0..4).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+    points[3] = points[1];
+    let evals = (0..4).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+
+    lagrange_interpolate(&points, &evals);
+}
+
+#[test]
+#[should_panic(expected = "lagrange_interpolate_fast: duplicate x-coordinate at indices 0,2")]
+fn test_lagrange_interpolate_fast_duplicate_points() {
+    let rng = OsRng;
+
+    let mut points = (0..4).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+    points[2] = points[0];
+    let evals = (0..4).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+
+    lagrange_interpolate_fast(&points, &evals);
+}
+
+#[test]
+fn test_eval_polynomial_constant() {
+    let point = Fp::random(OsRng);
+    let coeff = Fp::random(OsRng);
+
+    assert_eq!(eval_polynomial(&[], point), Fp::ZERO);
+    assert_eq!(eval_polynomial(&[coeff], point), coeff);
+}
+
+#[test]
+fn test_best_fft() {
+    let k = 4; // Example value
+    let size = 1 << k;
+
+    // Generate random inputs
+    let mut a = (0..size).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let omega = Fp::random(OsRng);
+
+    // Run the FFT
+    best_fft(&mut a, omega, k);
+
+    // Check that the result is as expected (this will depend on your expected outcome)
+    // For now, just assert that the length is as expected
+    assert_eq!(a.len(), size);
+}
+
+#[test]
+fn test_best_ifft() {
+    for k in [1, 3, 6, 10] {
+        let mut omega = Fp::ROOT_OF_UNITY;
+        for _ in k..Fp::S {
+            omega = omega.square();
+        }
+        let omega_inv = omega.invert().unwrap();
+
+        let a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        best_fft(&mut b, omega, k);
+        best_ifft(&mut b, omega_inv, k);
+
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn test_compute_inner_product() {
+    let a = (0..1000).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let b = (0..1000).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    for len in [0, 1, 7, 1000] {
+        let expected = a[..len]
+            .iter()
+            .zip(b[..len].iter())
+            .fold(Fp::ZERO, |acc, (a, b)| acc + *a * b);
+        assert_eq!(compute_inner_product(&a[..len], &b[..len]), expected);
+    }
+}
+
+#[test]
+fn test_cpu_multiexp_with_window() {
+    let coeffs = (0..100).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..100)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+
+    let expected = small_multiexp(&coeffs, &bases);
+    for c in [1, 4, 12, MAX_MSM_WINDOW_BITS] {
+        assert_eq!(cpu_multiexp_with_window(&coeffs, &bases, c), expected);
+    }
+}
+
+#[test]
+#[should_panic(expected = "window size (17) must be between 1 and 16")]
+fn test_cpu_multiexp_with_window_too_wide() {
+    let bases = [Eq::random(OsRng).to_affine()];
+    cpu_multiexp_with_window(&[Fp::ONE], &bases, MAX_MSM_WINDOW_BITS + 1);
+}
+
+#[test]
+fn test_best_multiexp_batch() {
+    let coeffs = (0..300).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..300)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+
+    let ranges = [0..0, 0..1, 1..40, 40..300];
+    let jobs = ranges
+        .iter()
+        .map(|r| (&coeffs[r.clone()], &bases[r.clone()]))
+        .collect::<Vec<_>>();
+
+    let results = best_multiexp_batch(&jobs);
+    assert_eq!(results.len(), jobs.len());
+    for (result, (coeffs, bases)) in results.into_iter().zip(jobs) {
+        assert_eq!(result, best_multiexp_cpu(coeffs, bases));
+    }
+}
+
+#[test]
+fn test_best_multiexp_affine_batch() {
+    let coeffs = (0..100).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..100)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+
+    let ranges = [0..0, 0..1, 1..40, 40..100];
+    let jobs = ranges
+        .iter()
+        .map(|r| (&coeffs[r.clone()], &bases[r.clone()]))
+        .collect::<Vec<_>>();
+
+    let results = best_multiexp_affine_batch(&jobs);
+    assert_eq!(results.len(), jobs.len());
+    for (result, (coeffs, bases)) in results.into_iter().zip(jobs) {
+        assert_eq!(result, best_multiexp_cpu(coeffs, bases).to_affine());
+    }
+}
+
+#[test]
+fn test_kate_division_checked() {
+    let b = Fp::random(OsRng);
+    let mut a = (0..10).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    // A random polynomial is not divisible by X - b; the remainder is a(b).
+    let eval = eval_polynomial(&a, b);
+    assert_eq!(kate_division_checked(&a, b), Err(eval));
+
+    // Subtracting the remainder makes it divisible.
+    a[0] -= eval;
+    assert_eq!(kate_division_checked(&a, b), Ok(kate_division(&a, b)));
+}
+
+#[cfg(feature = "icicle_gpu")]
+#[test]
+fn test_gpu_supports_curve() {
+    assert!(gpu_supports_curve::<halo2curves::bn256::G1Affine>());
+    assert!(!gpu_supports_curve::<EqAffine>());
+    assert!(!gpu_supports_curve::<halo2curves::pasta::EpAffine>());
+}
+
+#[test]
+fn test_fft_arbitrary() {
+    for n in [1, 3, 6, 10, 12] {
+        let omega = Fp::random(OsRng);
+        let a = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+        let mut b = a.clone();
+        fft_arbitrary(&mut b, omega);
+
+        for (k, b) in b.iter().enumerate() {
+            assert_eq!(*b, eval_polynomial(&a, omega.pow_vartime([k as u64])));
+        }
+    }
+}
+
+#[test]
+fn test_twiddle_cache() {
+    let k = 6;
+    let mut omega = Fp::ROOT_OF_UNITY;
+    for _ in k..Fp::S {
+        omega = omega.square();
+    }
+
+    let cache = TwiddleCache::new();
+    for _ in 0..2 {
+        let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        best_fft(&mut a, omega, k);
+        cache.fft_cached(&mut b, omega, k);
+        assert_eq!(a, b);
+    }
+
+    cache.clear();
+    let mut a = vec![Fp::ONE; 1 << k];
+    cache.fft_cached(&mut a, omega, k);
+    assert_eq!(a[0], Fp::from(1 << k));
+}
+
+#[test]
+fn test_poly_mul() {
+    let k = 4;
+    let mut omega = Fp::ROOT_OF_UNITY;
+    for _ in k..Fp::S {
+        omega = omega.square();
+    }
+
+    for (a_len, b_len) in [(1, 1), (3, 5), (8, 9), (1, 16)] {
+        let a = (0..a_len).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let b = (0..b_len).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+        let mut expected = vec![Fp::ZERO; a_len + b_len - 1];
+        for (i, a) in a.iter().enumerate() {
+            for (j, b) in b.iter().enumerate() {
+                expected[i + j] += *a * b;
+            }
+        }
+
+        assert_eq!(poly_mul(&a, &b, omega, k), expected);
+    }
+}
+
+#[test]
+fn test_poly_mul_toom3() {
+    for degree in 1..=300 {
+        let a = (0..=degree).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let b = (0..=degree).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let expected = poly_mul_schoolbook(&a, &b);
+
+        assert_eq!(poly_mul_toom3(&a, &b), expected);
+
+        let log_n = log2_ceil(expected.len());
+        let omega = (log_n..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+        assert_eq!(poly_mul(&a, &b, omega, log_n), expected);
+
+        assert_eq!(poly_mul_auto(&a, &b), expected);
+    }
+}
+
+#[test]
+fn test_multiexp_with_fallback() {
+    let coeffs = (0..50).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..50)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+
+    let result = multiexp_with_fallback(&coeffs, &bases, || Err("no device found"));
+    assert_eq!(result, best_multiexp_cpu(&coeffs, &bases));
+}
+
+#[test]
+fn test_best_multiexp() {
+    let coeffs = (0..50).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let mut bases = (0..50)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+    assert_eq!(
+        best_multiexp(&coeffs, &bases),
+        best_multiexp_cpu(&coeffs, &bases)
+    );
+
+    bases[0] = Eq::identity().to_affine();
+    assert_eq!(
+        best_multiexp(&coeffs, &bases),
+        best_multiexp_cpu(&coeffs, &bases)
+    );
+}
+
+#[test]
+fn test_cpu_multiexp_with_reprs() {
+    let coeffs = (0..100).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let bases = (0..100)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+    let reprs = coeffs.iter().map(|a| a.to_repr()).collect::<Vec<_>>();
+
+    assert_eq!(
+        cpu_multiexp_with_reprs(&reprs, &bases),
+        best_multiexp_cpu(&coeffs, &bases)
+    );
+}
+
+#[test]
+fn test_lagrange_interpolate_fast() {
+    for n in [0, 1, 2, 5, 64] {
+        let points = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let evals = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+        assert_eq!(
+            lagrange_interpolate_fast(&points, &evals),
+            lagrange_interpolate(&points, &evals)
+        );
+    }
+}
+
+#[test]
+fn test_eval_polynomial_batch() {
+    let poly = (0..50).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    for n in [0, 1, 3, 100] {
+        let points = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let expected = points
+            .iter()
+            .map(|point| eval_polynomial(&poly, *point))
+            .collect::<Vec<_>>();
+        assert_eq!(eval_polynomial_batch(&poly, &points), expected);
+    }
+}
+
+#[test]
+fn test_fft_with_twiddles() {
+    for k in [1, 4, 9] {
+        let mut omega = Fp::ROOT_OF_UNITY;
+        for _ in k..Fp::S {
+            omega = omega.square();
+        }
+
+        let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        best_fft(&mut a, omega, k);
+        fft_with_twiddles(&mut b, k, &compute_twiddles(omega, k));
+
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn test_coset_fft() {
+    let k = 5;
+    let mut omega = Fp::ROOT_OF_UNITY;
+    for _ in k..Fp::S {
+        omega = omega.square();
+    }
+    let g = Fp::random(OsRng);
+
+    let a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let mut b = a.clone();
+    coset_fft(&mut b, omega, g, k);
+    for (i, b) in b.iter().enumerate() {
+        assert_eq!(*b, eval_polynomial(&a, g * omega.pow_vartime([i as u64])));
+    }
+
+    coset_ifft(&mut b, omega.invert().unwrap(), g.invert().unwrap(), k);
+    assert_eq!(a, b);
+}
+
+#[test]
+fn test_log2() {
+    for k in 0..usize::BITS {
+        let num = 1usize << k;
+        assert_eq!(log2_floor(num), k);
+        assert_eq!(log2_ceil(num), k);
+        if k > 1 {
+            assert_eq!(log2_floor(num - 1), k - 1);
+            assert_eq!(log2_ceil(num - 1), k);
+            assert_eq!(log2_floor(num + 1), k);
+            assert_eq!(log2_ceil(num + 1), k + 1);
+        }
+    }
+    assert_eq!(log2_floor(usize::MAX), usize::BITS - 1);
+    assert_eq!(log2_ceil(usize::MAX), usize::BITS);
+}
+
+#[test]
+fn test_cpu_multiexp_deterministic() {
+    for n in [0, 1, 63, 200] {
+        let coeffs = (0..n).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let bases = (0..n)
+            .map(|_| Eq::random(OsRng).to_affine())
+            .collect::<Vec<EqAffine>>();
+
+        assert_eq!(
+            cpu_multiexp_deterministic(&coeffs, &bases),
+            best_multiexp_cpu(&coeffs, &bases)
+        );
+    }
+}
+
+#[test]
+fn test_fft_into() {
+    let mut scratch = vec![Fp::ONE; 1000];
+    for k in [6, 3] {
+        let mut omega = Fp::ROOT_OF_UNITY;
+        for _ in k..Fp::S {
+            omega = omega.square();
+        }
+
+        let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        best_fft(&mut a, omega, k);
+        fft_into(&mut b, omega, k, &mut scratch);
+
+        assert_eq!(a, b);
+        assert_eq!(scratch, compute_twiddles(omega, k));
+    }
+}
+
+#[test]
+fn test_cpu_multiexp_sparse() {
+    let coeffs = (0..300)
+        .map(|i| match i % 3 {
+            0 => Fp::ZERO,
+            1 => Fp::ONE,
+            _ => Fp::random(OsRng),
+        })
+        .collect::<Vec<_>>();
+    let bases = (0..300)
+        .map(|_| Eq::random(OsRng).to_affine())
+        .collect::<Vec<EqAffine>>();
+
+    assert_eq!(
+        cpu_multiexp_sparse(&coeffs, &bases),
+        best_multiexp_cpu(&coeffs, &bases)
+    );
+}
+
+#[test]
+fn test_parallel_batch_invert() {
+    let mut v = (0..100).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    v[17] = Fp::ZERO;
+    let expected = v
+        .iter()
+        .map(|x| x.invert().unwrap_or(Fp::ZERO))
+        .collect::<Vec<_>>();
+
+    parallel_batch_invert(&mut v);
+    assert_eq!(v, expected);
+}
+
+#[test]
+#[should_panic(expected = "omega must have order 2^4")]
+fn test_best_fft_checked_wrong_omega() {
+    let k = 4;
+    let mut omega = Fp::ROOT_OF_UNITY;
+    for _ in (k - 1)..Fp::S {
+        omega = omega.square();
+    }
+
+    let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    best_fft_checked(&mut a, omega, k);
+}
+
+#[test]
+fn test_best_fft_checked() {
+    for k in [0, 3, 8] {
+        let mut omega = Fp::ROOT_OF_UNITY;
+        for _ in k..Fp::S {
+            omega = omega.square();
+        }
+
+        let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+        best_fft_checked(&mut a, omega, k);
+    }
+}
+
+#[test]
+fn test_get_at() {
+    let repr = Fp::random(OsRng).to_repr();
+    let bit = |i: usize| -> usize {
+        repr.as_ref()
+            .get(i / 8)
+            .map_or(0, |byte| ((byte >> (i % 8)) & 1) as usize)
+    };
+
+    for c in [1, 3, 8, 13, 31, 32] {
+        for segment in 0..=(Fp::NUM_BITS as usize / c) {
+            let expected = (0..c).fold(0, |acc, i| acc | (bit(segment * c + i) << i));
+            assert_eq!(get_at::<Fp>(segment, c, &repr), expected);
+        }
+    }
+}
+
+#[test]
+fn test_window_digits() {
+    let reprs: Vec<_> = (0..10).map(|_| Fp::random(OsRng).to_repr()).collect();
+    for c in [1, 3, 8, 13, 31, 32] {
+        let digits = window_digits::<Fp>(&reprs, c);
+        let segments = Fp::NUM_BITS as usize / c + 1;
+        assert_eq!(digits.len(), reprs.len() * segments);
+        for segment in 0..segments {
+            for (i, repr) in reprs.iter().enumerate() {
+                let expected = get_at::<Fp>(segment, c, repr);
+                assert_eq!(digits[segment * reprs.len() + i] as usize, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_divide_by_vanishing_poly() {
+    let k = 4;
+    let mut omega = Fp::ROOT_OF_UNITY;
+    for _ in k..Fp::S {
+        omega = omega.square();
+    }
+
+    let n = 4;
+    let mut vanishing = vec![Fp::ZERO; n + 1];
+    vanishing[0] = -Fp::ONE;
+    vanishing[n] = Fp::ONE;
+
+    let q = (0..10).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let a = poly_mul(&q, &vanishing, omega, k);
+
+    assert_eq!(divide_by_vanishing_poly(&a, n), q);
+}
+
+#[test]
+fn test_parallelize_with_progress() {
+    use std::sync::atomic::AtomicUsize;
+
+    let mut v = vec![0usize; 1000];
+    let chunks = AtomicUsize::new(0);
+    parallelize_with_progress(
+        &mut v,
+        |v, start| {
+            for (i, v) in v.iter_mut().enumerate() {
+                *v = start + i;
+            }
+        },
+        |_| {
+            chunks.fetch_add(1, Ordering::Relaxed);
+        },
+    );
+
+    assert_eq!(v, (0..1000).collect::<Vec<_>>());
+    assert_eq!(
+        chunks.load(Ordering::Relaxed),
+        std::cmp::min(multicore::current_num_threads(), 1000)
+    );
+}
+
+#[test]
+fn test_parallelize_map() {
+    let threads = multicore::current_num_threads();
+    for len in [0, 1, threads - 1, threads, 3 * threads + 1, 1000] {
+        let v = (0..len).collect::<Vec<usize>>();
+        let chunks = parallelize_map(&v, |chunk, offset| {
+            assert_eq!(chunk.first().copied().unwrap_or(offset), offset);
+            (offset, chunk.len())
+        });
+
+        // The chunks are returned in order and cover the slice.
+        assert_eq!(chunks.len(), std::cmp::min(threads, len));
+        let mut expected_offset = 0;
+        for &(offset, chunk_len) in chunks.iter() {
+            assert_eq!(offset, expected_offset);
+            expected_offset += chunk_len;
+        }
+        assert_eq!(expected_offset, len);
+
+        // The chunk sizes differ by at most one, with the larger ones first.
+        for pair in chunks.windows(2) {
+            assert!(pair[0].1 == pair[1].1 || pair[0].1 == pair[1].1 + 1);
+        }
+    }
+}
+
+#[test]
+fn test_cpu_multiexp_compressed() {
+    let rng = OsRng;
+    for n in [1, 5, 100] {
+        let coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+        let mut compressed: Vec<_> = bases.iter().map(|base| base.to_bytes()).collect();
+
+        assert_eq!(
+            cpu_multiexp_compressed(&coeffs, &compressed).unwrap(),
+            best_multiexp_cpu(&coeffs, &bases)
+        );
+
+        // The x-coordinate is not canonical, as it exceeds the field modulus.
+        compressed[n - 1].as_mut().fill(0xff);
+        assert!(cpu_multiexp_compressed(&coeffs, &compressed).is_err());
+    }
+}
+
+#[test]
+fn test_small_multiexp_wnaf() {
+    let rng = OsRng;
+    for n in 1..=8 {
+        let mut coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+        assert_eq!(
+            small_multiexp_wnaf(&coeffs, &bases),
+            small_multiexp(&coeffs, &bases)
+        );
+
+        coeffs[0] = Fp::ZERO;
+        coeffs[n - 1] = -Fp::ONE;
+        assert_eq!(
+            small_multiexp_wnaf(&coeffs, &bases),
+            small_multiexp(&coeffs, &bases)
+        );
+    }
+}
+
+#[test]
+fn test_best_fft_many() {
+    let rng = OsRng;
+    let log_n = 6;
+    let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n), 0, 0, 0]);
+
+    let mut polys: Vec<Vec<Fp>> = (0..4)
+        .map(|_| (0..(1 << log_n)).map(|_| Fp::random(rng)).collect())
+        .collect();
+    let expected: Vec<Vec<Fp>> = polys
+        .iter()
+        .map(|poly| {
+            let mut poly = poly.clone();
+            best_fft(&mut poly, omega, log_n);
+            poly
+        })
+        .collect();
+
+    let mut batches: Vec<&mut [Fp]> = polys.iter_mut().map(|poly| &mut poly[..]).collect();
+    best_fft_many(&mut batches, omega, log_n);
+    assert_eq!(polys, expected);
+}
+
+#[test]
+fn test_thread_pool_variants() {
+    let rng = OsRng;
+    let pool = ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+
+    let log_n = 6;
+    let n = 1 << log_n;
+    let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n), 0, 0, 0]);
+    let a: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let b: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let x = Fp::random(rng);
+
+    let mut expected = a.clone();
+    best_fft(&mut expected, omega, log_n);
+    let mut actual = a.clone();
+    best_fft_in(&pool, &mut actual, omega, log_n);
+    assert_eq!(actual, expected);
+
+    assert_eq!(eval_polynomial_in(&pool, &a, x), eval_polynomial(&a, x));
+    assert_eq!(
+        compute_inner_product_in(&pool, &a, &b),
+        compute_inner_product(&a, &b)
+    );
+
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+    assert_eq!(
+        best_multiexp_cpu_in(&pool, &a, &bases),
+        best_multiexp_cpu(&a, &bases)
+    );
+
+    // The work is split across the threads of the pool, not the global pool.
+    let mut v = vec![0usize; n];
+    parallelize_in(&pool, &mut v, |v, _| v[0] = 1);
+    assert_eq!(v.iter().sum::<usize>(), 3);
+}
+
+#[test]
+fn test_powers_bounded() {
+    let base = Fp::random(OsRng);
+    for n in [0, 1, 7, 1000] {
+        assert_eq!(
+            powers_bounded(base, n),
+            powers(base).take(n).collect::<Vec<_>>()
+        );
+    }
+
+    let mut expected = vec![Fp::ZERO; 10];
+    expected[0] = Fp::ONE;
+    assert_eq!(powers_bounded(Fp::ZERO, 10), expected);
+    assert_eq!(powers(Fp::ZERO).take(10).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_eval_lagrange() {
+    let k = 5;
+    let omega = (k..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+    let evals = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    let mut coeffs = evals.clone();
+    best_ifft(&mut coeffs, omega.invert().unwrap(), k);
+
+    let z = Fp::random(OsRng);
+    assert_eq!(eval_lagrange(&evals, omega, z), eval_polynomial(&coeffs, z));
+
+    let z = omega.pow_vartime([3]);
+    assert_eq!(eval_lagrange(&evals, omega, z), evals[3]);
+}
+
+#[test]
+fn test_msm_accumulator() {
+    let rng = OsRng;
+    let coeffs: Vec<Fp> = (0..100).map(|_| Fp::random(rng)).collect();
+    let bases: Vec<EqAffine> = (0..100).map(|_| Eq::random(rng).to_affine()).collect();
+    let expected = best_multiexp_cpu(&coeffs, &bases);
+
+    let mut acc = MsmAccumulator::with_capacity(7);
+    for (coeffs, bases) in coeffs.chunks(15).zip(bases.chunks(15)) {
+        acc.extend(coeffs.iter().copied().zip(bases.iter().copied()));
+    }
+    assert_eq!(acc.finalize(), expected);
+
+    let mut acc = MsmAccumulator::new();
+    for (coeff, base) in coeffs.iter().zip(bases.iter()) {
+        acc.push(*coeff, *base);
+    }
+    assert_eq!(acc.finalize(), expected);
+
+    assert_eq!(
+        MsmAccumulator::<EqAffine>::new().finalize(),
+        Eq::identity()
+    );
+}
+
+#[test]
+fn test_bitreverse_permute() {
+    assert_eq!(bitreverse(0b0011, 4), 0b1100);
+    assert_eq!(bitreverse(0b10110, 3), 0b011);
+
+    for log_n in 0..6 {
+        let a: Vec<usize> = (0..(1 << log_n)).collect();
+        let mut b = a.clone();
+        bitreverse_permute(&mut b, log_n);
+        for (i, b) in b.iter().enumerate() {
+            assert_eq!(*b, bitreverse(i, log_n as usize));
+        }
+        bitreverse_permute(&mut b, log_n);
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn test_kate_division_multi() {
+    let a = (0..20).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let roots = (0..9).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    let quotients = kate_division_multi(&a, &roots);
+    assert_eq!(quotients.len(), roots.len());
+    for (q, root) in quotients.iter().zip(roots.iter()) {
+        assert_eq!(*q, kate_division(&a, *root));
+    }
+}
+
+#[test]
+fn test_best_fft_non_static() {
+    use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
+
+    // A group element that borrows its label, so that it is not `'static`.
+    #[derive(Clone, Copy)]
+    struct Labelled<'a> {
+        value: Fp,
+        label: &'a str,
+    }
+
+    impl<'a, 'r> Add<&'r Labelled<'a>> for Labelled<'a> {
+        type Output = Self;
+        fn add(self, rhs: &'r Labelled<'a>) -> Self {
+            Labelled {
+                value: self.value + rhs.value,
+                ..self
+            }
+        }
+    }
+
+    impl<'a, 'r> Sub<&'r Labelled<'a>> for Labelled<'a> {
+        type Output = Self;
+        fn sub(self, rhs: &'r Labelled<'a>) -> Self {
+            Labelled {
+                value: self.value - rhs.value,
+                ..self
+            }
+        }
+    }
+
+    impl<'a, 'r> AddAssign<&'r Labelled<'a>> for Labelled<'a> {
+        fn add_assign(&mut self, rhs: &'r Labelled<'a>) {
+            self.value += rhs.value;
+        }
+    }
+
+    impl<'a, 'r> SubAssign<&'r Labelled<'a>> for Labelled<'a> {
+        fn sub_assign(&mut self, rhs: &'r Labelled<'a>) {
+            self.value -= rhs.value;
+        }
+    }
+
+    impl<'a, 'r> Mul<&'r Fp> for Labelled<'a> {
+        type Output = Self;
+        fn mul(self, rhs: &'r Fp) -> Self {
+            Labelled {
+                value: self.value * rhs,
+                ..self
+            }
+        }
+    }
+
+    impl<'a, 'r> MulAssign<&'r Fp> for Labelled<'a> {
+        fn mul_assign(&mut self, rhs: &'r Fp) {
+            self.value *= rhs;
+        }
+    }
+
+    let k = 8;
+    let omega = (k..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+    let values = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let mut expected = values.clone();
+    best_fft(&mut expected, omega, k);
+
+    let label = String::from("borrowed");
+    let mut a = values
+        .iter()
+        .map(|value| Labelled {
+            value: *value,
+            label: &label,
+        })
+        .collect::<Vec<_>>();
+    best_fft(&mut a, omega, k);
+
+    assert!(a.iter().all(|a| a.label == "borrowed"));
+    assert_eq!(a.iter().map(|a| a.value).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_multiexp_serial_soa() {
+    let rng = OsRng;
+    for n in [1, 3, 10, 100, 1000] {
+        let coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+        assert_eq!(
+            multiexp_serial_soa_pub(&coeffs, &bases),
+            multiexp_serial_pub(&coeffs, &bases)
+        );
+    }
+
+    // From 7-bit windows on, there are more buckets than bits in one bitset word.
+    let coeffs: Vec<Fp> = (0..50).map(|_| Fp::random(rng)).collect();
+    let bases: Vec<EqAffine> = (0..50).map(|_| Eq::random(rng).to_affine()).collect();
+    let reprs: Vec<_> = coeffs.iter().map(|a| a.to_repr()).collect();
+    for c in [1, 7, 12] {
+        let mut expected = Eq::identity();
+        multiexp_serial(&reprs, &bases, &mut expected, c);
+        let mut actual = Eq::identity();
+        multiexp_serial_soa(&reprs, &bases, &mut actual, c);
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn test_try_best_fft() {
+    let k = 4;
+    let omega = (k..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+
+    let mut a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+    let mut expected = a.clone();
+    best_fft(&mut expected, omega, k);
+    assert_eq!(try_best_fft(&mut a, omega, k), Ok(()));
+    assert_eq!(a, expected);
+
+    let mut a = vec![Fp::ZERO; 15];
+    assert_eq!(
+        try_best_fft(&mut a, omega, k),
+        Err(FftError::BadLength {
+            got: 15,
+            expected: 16
+        })
+    );
+    assert_eq!(
+        try_best_fft(&mut a, omega, 64),
+        Err(FftError::DomainTooLarge { log_n: 64 })
+    );
+}
+
+#[test]
+fn test_newton_interpolator() {
+    let rng = OsRng;
+    let points = (0..8).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+    let evals = (0..8).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+
+    let mut interpolator = NewtonInterpolator::new();
+    assert!(interpolator.to_coeffs().is_empty());
+    for i in 0..points.len() {
+        interpolator.add_point(points[i], evals[i]);
+        assert_eq!(
+            interpolator.to_coeffs(),
+            lagrange_interpolate(&points[..=i], &evals[..=i])
+        );
+    }
+}
+
+#[test]
+fn test_best_fft_radix4() {
+    for log_n in 0..=10 {
+        let omega = (log_n..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+        let a = (0..(1 << log_n)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+        let mut expected = a.clone();
+        best_fft(&mut expected, omega, log_n);
+        let mut actual = a.clone();
+        best_fft_radix4(&mut actual, omega, log_n);
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn test_msm_context() {
+    let rng = OsRng;
+    let bases: Vec<EqAffine> = (0..200).map(|_| Eq::random(rng).to_affine()).collect();
+    let context = MsmContext::new(&bases);
+
+    for _ in 0..3 {
+        let coeffs: Vec<Fp> = (0..200).map(|_| Fp::random(rng)).collect();
+        assert_eq!(context.msm(&coeffs), best_multiexp_cpu(&coeffs, &bases));
+    }
+}
+
+#[test]
+fn test_msm_context_sizes() {
+    let rng = OsRng;
+    for n in [0, 1, 3, 40] {
+        let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+        let coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        assert_eq!(
+            MsmContext::new(&bases).msm(&coeffs),
+            best_multiexp_cpu(&coeffs, &bases)
+        );
+    }
+}
+
+#[test]
+fn test_cpu_multiexp_reduce() {
+    use ff::FromUniformBytes;
+    use rand_core::RngCore;
+
+    let mut rng = OsRng;
+    let mut wide: Vec<[u64; 4]> = (0..50)
+        .map(|_| [rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()])
+        .collect();
+    wide[0] = [u64::MAX; 4];
+    wide[1] = [0; 4];
+    let bases: Vec<EqAffine> = (0..50).map(|_| Eq::random(rng).to_affine()).collect();
+
+    let coeffs: Vec<Fp> = wide
+        .iter()
+        .map(|wide| {
+            let mut bytes = [0u8; 64];
+            for (bytes, limb) in bytes.chunks_mut(8).zip(wide.iter()) {
+                bytes.copy_from_slice(&limb.to_le_bytes());
+            }
+            Fp::from_uniform_bytes(&bytes)
+        })
+        .collect();
+
+    assert_eq!(
+        cpu_multiexp_reduce(&wide, &bases),
+        best_multiexp_cpu(&coeffs, &bases)
+    );
+}
+
+#[test]
+fn test_fft_serial_cutoff() {
+    let k = 12;
+    let omega = (k..Fp::S).fold(Fp::ROOT_OF_UNITY, |omega, _| omega.square());
+    let a = (0..(1 << k)).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
+
+    let mut expected = a.clone();
+    serial::fft(&mut expected, omega, k);
+
+    for cutoff in [2, 8, 1 << 10, DEFAULT_FFT_SERIAL_CUTOFF] {
+        set_fft_serial_cutoff(cutoff);
+        let mut actual = a.clone();
+        best_fft(&mut actual, omega, k);
+        assert_eq!(actual, expected);
+    }
+}
+
+#[test]
+fn test_serial_matches_parallel() {
+    let rng = OsRng;
+    let log_n = 5;
+    let n = 1 << log_n;
+    let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n), 0, 0, 0]);
+
+    let a: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let b: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let x = Fp::random(rng);
+
+    assert_eq!(serial::eval_polynomial(&a, x), eval_polynomial(&a, x));
+    assert_eq!(
+        serial::compute_inner_product(&a, &b),
+        compute_inner_product(&a, &b)
+    );
+
+    let mut expected = a.clone();
+    best_fft(&mut expected, omega, log_n);
+    let mut actual = a.clone();
+    serial::fft(&mut actual, omega, log_n);
+    assert_eq!(actual, expected);
+
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+    assert_eq!(
+        serial::multiexp_serial(&a, &bases),
+        best_multiexp_cpu(&a, &bases)
+    );
+}
+
+#[test]
+#[should_panic(expected = "g_to_lagrange: k = 33 exceeds the two-adicity of the scalar field (32)")]
+fn test_g_to_lagrange_k_too_large() {
+    g_to_lagrange::<EqAffine>(vec![], Fp::S + 1);
+}
+
+#[test]
+fn test_truncated_fft() {
+    let rng = OsRng;
+    let log_n = 10;
+    let n = 1 << log_n;
+    let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n), 0, 0, 0]);
+
+    for nonzero_len in [0, 1, 5, n / 8, n / 8 + 3, n / 4, n / 2, n] {
+        let mut expected: Vec<Fp> = (0..n)
+            .map(|i| if i < nonzero_len { Fp::random(rng) } else { Fp::ZERO })
+            .collect();
+        let mut actual = expected.clone();
+
+        best_fft(&mut expected, omega, log_n);
+        truncated_fft(&mut actual, omega, log_n, nonzero_len);
+        assert_eq!(actual, expected, "nonzero_len = {}", nonzero_len);
+    }
+}
+
+#[cfg(feature = "debug-tracing")]
+#[test]
+fn test_multiexp_serial_trace() {
+    let rng = OsRng;
+    let n = 100;
+    let coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+
+    let (result, trace) = multiexp_serial_trace(&coeffs, &bases);
+    let c = multiexp_window_size(n);
+    assert_eq!(trace.len(), Fp::NUM_BITS as usize / c + 1);
+    assert_eq!(trace.last(), Some(&result));
+    assert_eq!(result, multiexp_serial_pub(&coeffs, &bases));
+}
+
+#[test]
+fn test_big_endian_scalar_reprs() {
+    // The Pallas base field, with big-endian representations.
+    #[derive(ff::PrimeField)]
+    #[PrimeFieldModulus = "28948022309329048855892746252171976963363056481941560715954676764349967630337"]
+    #[PrimeFieldGenerator = "5"]
+    #[PrimeFieldReprEndianness = "big"]
+    struct BeFp([u64; 4]);
+
+    assert!(!repr_is_big_endian::<Fp>());
+    assert!(repr_is_big_endian::<BeFp>());
+
+    let rng = OsRng;
+    let scalars: Vec<Fp> = (0..10).map(|_| Fp::random(rng)).collect();
+    let be_scalars: Vec<BeFp> = scalars
+        .iter()
+        .map(|scalar| {
+            let mut repr = <BeFp as PrimeField>::Repr::default();
+            repr.as_mut().copy_from_slice(scalar.to_repr().as_ref());
+            repr.as_mut().reverse();
+            BeFp::from_repr(repr).unwrap()
+        })
+        .collect();
+
+    let reprs = scalars_to_reprs(&scalars);
+    let be_reprs = scalars_to_reprs(&be_scalars);
+    let be_reprs_serial = scalars_to_reprs_serial(&be_scalars);
+    let c = 7;
+    for ((repr, be_repr), be_repr_serial) in reprs.iter().zip(&be_reprs).zip(&be_reprs_serial) {
+        assert_eq!(repr.as_ref(), be_repr.as_ref());
+        assert_eq!(be_repr.as_ref(), be_repr_serial.as_ref());
+        for segment in 0..(Fp::NUM_BITS as usize / c + 1) {
+            assert_eq!(
+                get_at::<Fp>(segment, c, repr),
+                get_at::<BeFp>(segment, c, be_repr)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_inner_product_msm() {
+    let rng = OsRng;
+    let n = 50;
+    let scalars: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+
+    let expected = scalars
+        .iter()
+        .zip(bases.iter())
+        .fold(Eq::identity(), |acc, (scalar, base)| acc + *base * scalar);
+    assert_eq!(inner_product_msm(&scalars, &bases), expected);
+}
+
+/// Serializes the tests that change the global profiling settings.
+#[cfg(all(test, feature = "profiling"))]
+static PROFILING_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Gives a test exclusive use of the global profiling settings, with the
+/// profiling files written to a fresh directory, and restores the defaults
+/// when dropped.
+#[cfg(all(test, feature = "profiling"))]
+struct ProfilingTestGuard {
+    dir: PathBuf,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(all(test, feature = "profiling"))]
+impl ProfilingTestGuard {
+    fn new(name: &str) -> Self {
+        // A test that failed while holding the lock leaves nothing to clean up.
+        let lock = PROFILING_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!(
+            "halo2_profiling_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        set_profiling_path(dir.clone());
+        ProfilingTestGuard { dir, _lock: lock }
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+impl Drop for ProfilingTestGuard {
+    fn drop(&mut self) {
+        set_profiling_enabled(false);
+        set_profiling_mode(ProfilingMode::Rows);
+        clear_profiling_label();
+        *PROFILING_PATH.lock().unwrap() = None;
+        *PROFILING_ERROR_HANDLER.lock().unwrap() = None;
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+#[test]
+fn test_profiling_rows() {
+    let profiling = ProfilingTestGuard::new("rows");
+    set_profiling_label("test_profiling_rows");
+    set_profiling_enabled(true);
+
+    let mut a: Vec<Fp> = (0..1 << 5).map(|_| Fp::random(OsRng)).collect();
+    best_fft(&mut a, root_of_unity::<Fp>(5), 5);
+    let bases = vec![Eq::generator().to_affine(); 37];
+    let coeffs: Vec<Fp> = (0..37).map(|_| Fp::random(OsRng)).collect();
+    best_multiexp_cpu(&coeffs, &bases);
+    set_profiling_enabled(false);
+
+    // Other tests may log operations of their own meanwhile, so only the
+    // presence of the rows above is checked.
+    let read = |filename: &str| std::fs::read_to_string(profiling.dir.join(filename)).unwrap();
+    let has_row = |csv: &str, prefix: &str| {
+        csv.lines()
+            .skip(1)
+            .any(|line| line.starts_with(prefix) && line.ends_with(",test_profiling_rows"))
+    };
+
+    let ffts = read("cpu_fft_times.csv");
+    assert_eq!(
+        ffts.lines().next(),
+        Some("size,log_n,fft_type,total_duration (ms),label")
+    );
+    assert!(has_row(&ffts, "32,5,cpu,"));
+
+    let msms = read("cpu_msm_times.csv");
+    assert_eq!(msms.lines().next(), Some("num_coeffs,msm_duration,label"));
+    assert!(has_row(&msms, "37,"));
+}
+
+#[cfg(feature = "profiling-json")]
+#[test]
+fn test_profiling_json_lines() {
+    let profiling = ProfilingTestGuard::new("json");
+    set_profiling_label("test_profiling_json_lines");
+    set_profiling_enabled(true);
+
+    let mut a: Vec<Fp> = (0..1 << 5).map(|_| Fp::random(OsRng)).collect();
+    best_fft(&mut a, root_of_unity::<Fp>(5), 5);
+    let bases = vec![Eq::generator().to_affine(); 37];
+    let coeffs: Vec<Fp> = (0..37).map(|_| Fp::random(OsRng)).collect();
+    best_multiexp_cpu(&coeffs, &bases);
+    set_profiling_enabled(false);
+
+    // Every line must be a complete object. Other tests may log operations of
+    // their own meanwhile, so only the presence of the rows above is checked.
+    let read = |filename: &str| -> Vec<serde_json::Value> {
+        std::fs::read_to_string(profiling.dir.join(filename))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    };
+    let label = "test_profiling_json_lines";
+
+    let ffts = read("cpu_fft_times.jsonl");
+    assert!(ffts.iter().any(|row| {
+        row["size"] == 32 && row["logn"] == 5 && row["fft_type"] == "cpu" && row["label"] == label
+    }));
+    assert!(ffts.iter().all(|row| row["fft_duration"].is_f64()));
+
+    let msms = read("cpu_msm_times.jsonl");
+    assert!(msms.iter().any(|row| row["num_coeffs"] == "37" && row["label"] == label));
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_profiling_error_handler() {
+    use std::sync::atomic::AtomicUsize;
+
+    // The handler below is removed when the guard is dropped, even if the test
+    // fails.
+    let _profiling = ProfilingTestGuard::new("error_handler");
+
+    // Without a handler, errors are dropped.
+    report_profiling_error(ProfilingError::Fft("dropped".into()));
+
+    static REPORTED: AtomicUsize = AtomicUsize::new(0);
+    set_profiling_error_handler(Box::new(|e| {
+        assert_eq!(e.to_string(), "failed to log MSM stats: disk full");
+        assert!(e.source().is_some());
+        REPORTED.fetch_add(1, Ordering::Relaxed);
+    }));
+    report_profiling_error(ProfilingError::Msm(
+        io::Error::new(io::ErrorKind::Other, "disk full").into(),
+    ));
+    assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_multiexp2() {
+    let rng = OsRng;
+    let g = Eq::random(rng).to_affine();
+    let h = Eq::random(rng).to_affine();
+    let cases = [
+        (Fp::random(rng), Fp::random(rng), h),
+        (Fp::ZERO, Fp::random(rng), h),
+        (Fp::random(rng), Fp::ZERO, h),
+        (Fp::ZERO, Fp::ZERO, h),
+        (-Fp::ONE, Fp::random(rng), h),
+        // G + H is the doubling of G, or the identity.
+        (Fp::random(rng), Fp::random(rng), g),
+        (Fp::random(rng), Fp::random(rng), -g),
+    ];
+    for (a, b, h) in cases {
+        assert_eq!(multiexp2(a, g, b, h), g * a + h * b);
+    }
+}
+
+#[test]
+fn test_compute_twiddles_parallel() {
+    for log_n in [0, 1, 2, 5, 12] {
+        let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n.max(1)), 0, 0, 0]);
+        assert_eq!(
+            compute_twiddles(omega, log_n),
+            serial::compute_twiddles(omega, log_n),
+            "log_n = {}",
+            log_n
+        );
+    }
+}
+
+#[test]
+fn test_eval_polynomial_rlc() {
+    let rng = OsRng;
+    let r = Fp::random(rng);
+    let z = Fp::random(rng);
+    assert_eq!(eval_polynomial_rlc::<Fp>(&[], r, z), Fp::ZERO);
+
+    // Enough polynomials of differing lengths to split them across threads.
+    for num_polys in [1, 3, 3 * multicore::current_num_threads() + 1] {
+        let polys: Vec<Vec<Fp>> = (0..num_polys)
+            .map(|i| (0..(i % 7) * 5).map(|_| Fp::random(rng)).collect())
+            .collect();
+        let polys: Vec<&[Fp]> = polys.iter().map(|poly| &poly[..]).collect();
+
+        let expected = polys
+            .iter()
+            .zip(powers(r))
+            .fold(Fp::ZERO, |acc, (poly, r_i)| acc + eval_polynomial(poly, z) * r_i);
+        assert_eq!(eval_polynomial_rlc(&polys, r, z), expected);
+    }
+}
+
+#[test]
+fn test_lagrange_eval_at() {
+    let rng = OsRng;
+    assert_eq!(lagrange_eval_at::<Fp>(&[], &[], Fp::random(rng)), Fp::ZERO);
+
+    for n in [1, 2, 5, 20] {
+        let points: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        let evals: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+        let poly = lagrange_interpolate(&points, &evals);
+
+        let z = Fp::random(rng);
+        assert_eq!(lagrange_eval_at(&points, &evals, z), eval_polynomial(&poly, z));
+        assert_eq!(lagrange_eval_at(&points, &evals, points[n - 1]), evals[n - 1]);
+    }
+}
+
+#[test]
+fn test_parallelize_numa() {
+    let mut v = vec![0usize; 1000];
+    parallelize_numa(&mut v, |v, start| {
+        for (i, v) in v.iter_mut().enumerate() {
+            *v = start + i;
+        }
+    });
+    assert_eq!(v, (0..1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_root_of_unity() {
+    for k in [0, 1, 5, 12, Fp::S] {
+        let omega = root_of_unity::<Fp>(k);
+        let omega_inv = root_of_unity_inv::<Fp>(k);
+        assert_eq!(omega * omega_inv, Fp::ONE);
+
+        // omega has order exactly 2^k.
+        let omega_half = (0..k.saturating_sub(1)).fold(omega, |omega, _| omega.square());
+        if k > 0 {
+            assert_eq!(omega_half, -Fp::ONE);
+        }
+        assert_eq!(omega_half.square(), Fp::ONE);
+    }
+}
+
+/// With `fast-msm`, `multiexp_serial` skips the bounds checks on its buckets,
+/// while `multiexp_serial_soa` keeps them; their results must agree, including
+/// for the largest window values.
+#[cfg(feature = "fast-msm")]
+#[test]
+fn test_multiexp_serial_unchecked() {
+    let rng = OsRng;
+    let n = 64;
+    let mut coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    coeffs[0] = -Fp::ONE;
+    coeffs[1] = Fp::ZERO;
+    coeffs[2] = Fp::from(u64::MAX);
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+    let reprs = scalars_to_reprs(&coeffs);
+
+    for c in 1..=16 {
+        let mut expected = Eq::identity();
+        multiexp_serial_soa(&reprs, &bases, &mut expected, c);
+        let mut actual = Eq::identity();
+        multiexp_serial(&reprs, &bases, &mut actual, c);
+        assert_eq!(actual, expected, "c = {}", c);
+    }
+}
+
+#[test]
+fn test_fft_plan() {
+    let rng = OsRng;
+    for log_n in [0, 1, 4, 10] {
+        let plan = FftPlan::new(root_of_unity::<Fp>(log_n), log_n);
+        assert_eq!(plan.num_passes(), log_n);
+
+        // The plan is reused across transforms.
+        for _ in 0..2 {
+            let a: Vec<Fp> = (0..(1 << log_n)).map(|_| Fp::random(rng)).collect();
+            let mut expected = a.clone();
+            best_fft(&mut expected, root_of_unity(log_n), log_n);
+
+            let mut stepped = a.clone();
+            for pass in 0..plan.num_passes() {
+                plan.step(&mut stepped, pass);
+            }
+            assert_eq!(stepped, expected);
+
+            let mut all = a;
+            plan.run_all(&mut all);
+            assert_eq!(all, expected);
+        }
+    }
+}
+
+#[test]
+fn test_interpolate_over_subgroup() {
+    let rng = OsRng;
+    for log_n in [0, 1, 3, 8] {
+        let omega = root_of_unity::<Fp>(log_n);
+        let coeffs: Vec<Fp> = (0..(1 << log_n)).map(|_| Fp::random(rng)).collect();
+        let mut evals = coeffs.clone();
+        best_fft(&mut evals, omega, log_n);
+
+        assert_eq!(interpolate_over_subgroup(&evals, omega, log_n), coeffs);
+    }
+
+    // Agrees with interpolation over the same points as arbitrary points.
+    let log_n = 3;
+    let omega = root_of_unity::<Fp>(log_n);
+    let evals: Vec<Fp> = (0..(1 << log_n)).map(|_| Fp::random(rng)).collect();
+    let points = powers_bounded(omega, 1 << log_n);
+    assert_eq!(
+        interpolate_over_subgroup(&evals, omega, log_n),
+        lagrange_interpolate(&points, &evals)
+    );
+}
+
+#[test]
+fn test_serialize_msm_inputs() {
+    let rng = OsRng;
+    let n = 100;
+    let coeffs: Vec<Fp> = (0..n).map(|_| Fp::random(rng)).collect();
+    let bases: Vec<EqAffine> = (0..n).map(|_| Eq::random(rng).to_affine()).collect();
+
+    let mut bytes = vec![];
+    serialize_msm_inputs(&coeffs, &bases, &mut bytes).unwrap();
+    let (read_coeffs, read_bases) =
+        deserialize_msm_inputs::<EqAffine, _>(&mut bytes.as_slice()).unwrap();
+    assert_eq!(read_coeffs, coeffs);
+    assert_eq!(read_bases, bases);
+    assert_eq!(
+        best_multiexp_cpu(&read_coeffs, &read_bases),
+        best_multiexp_cpu(&coeffs, &bases)
     );
 
-    let timer = Instant::now();
+    // Truncated inputs and other versions are rejected.
+    assert!(deserialize_msm_inputs::<EqAffine, _>(&mut &bytes[..bytes.len() - 1]).is_err());
+    bytes[4] += 1;
+    assert!(deserialize_msm_inputs::<EqAffine, _>(&mut bytes.as_slice()).is_err());
+}
 
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+#[test]
+fn test_write_profiling_csv_concurrently() {
+    let profiling = ProfilingTestGuard::new("concurrently");
+    // An absolute filename is used as is.
+    let path = profiling.dir.join("concurrently.csv");
 
-    fn bitreverse(mut n: usize, l: usize) -> usize {
-        let mut r = 0;
-        for _ in 0..l {
-            r = (r << 1) | (n & 1);
-            n >>= 1;
-        }
-        r
+    // Long rows, which a writer flushes in several writes.
+    let threads: Vec<_> = (0..8)
+        .map(|thread: usize| {
+            let filename = path.to_str().unwrap().to_string();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    let value = thread.to_string().repeat(10_000);
+                    write_profiling_csv(&filename, &["thread", "value"], |wtr| {
+                        wtr.write_record(&[thread.to_string(), value])
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
     }
 
-    let threads = multicore::current_num_threads();
-    let log_threads = log2_floor(threads);
-    let n = a.len();
-    assert_eq!(n, 1 << log_n);
-
-    for k in 0..n {
-        let rk = bitreverse(k, log_n as usize);
-        if k < rk {
-            a.swap(rk, k);
-        }
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut lines = contents.lines();
+    assert_eq!(lines.next(), Some("thread,value"));
+    let mut rows = 0;
+    for line in lines {
+        let (thread, value) = line.split_once(',').unwrap();
+        assert_eq!(value, thread.repeat(10_000));
+        rows += 1;
     }
+    assert_eq!(rows, 800);
 
-    // precompute twiddle factors
-    let twiddles: Vec<_> = (0..(n / 2))
-        .scan(Scalar::ONE, |w, _| {
-            let tw = *w;
-            *w *= &omega;
-            Some(tw)
-        })
-        .collect();
+    // A removed file is recreated, header included, by the next write.
+    write_profiling_csv(path.to_str().unwrap(), &["thread", "value"], |wtr| {
+        wtr.write_record(&["8", "8"])
+    })
+    .unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().collect::<Vec<_>>(), ["thread,value", "8,8"]);
+}
 
-    if log_n <= log_threads {
-        let mut chunk = 2_usize;
-        let mut twiddle_chunk = n / 2;
-        for _ in 0..log_n {
-            a.chunks_mut(chunk).for_each(|coeffs| {
-                let (left, right) = coeffs.split_at_mut(chunk / 2);
-
-                // case when twiddle factor is one
-                let (a, left) = left.split_at_mut(1);
-                let (b, right) = right.split_at_mut(1);
-                let t = b[0];
-                b[0] = a[0];
-                a[0] += &t;
-                b[0] -= &t;
-
-                left.iter_mut()
-                    .zip(right.iter_mut())
-                    .enumerate()
-                    .for_each(|(i, (a, b))| {
-                        let mut t = *b;
-                        t *= &twiddles[(i + 1) * twiddle_chunk];
-                        *b = *a;
-                        *a += &t;
-                        *b -= &t;
-                    });
-            });
-            chunk *= 2;
-            twiddle_chunk /= 2;
+#[cfg(feature = "profiling")]
+#[test]
+fn test_profiling_histogram() {
+    let _profiling = ProfilingTestGuard::new("histogram");
+    let ms = Duration::from_millis;
+    let mut summary = OpSummary::new(ms(5));
+    summary.record(ms(2));
+    summary.record(ms(9));
+    assert_eq!(
+        summary,
+        OpSummary {
+            count: 3,
+            total: ms(16),
+            min: ms(2),
+            max: ms(9),
         }
-    } else {
-        recursive_butterfly_arithmetic(a, n, 1, &twiddles)
+    );
+
+    // In histogram mode, rows are aggregated rather than written. Profiling
+    // itself stays disabled, so no other test records operations.
+    set_profiling_mode(ProfilingMode::Histogram);
+    for duration in [ms(3), ms(1)] {
+        record_profiled_op(("fft", 1 << 20, Device::Cpu), duration, || {
+            panic!("no row is written in histogram mode")
+        });
     }
-    let total_fft_time = timer.elapsed();
-    stat_collector.fft_duration = total_fft_time.as_secs_f64();
-    let _ = log_fft_stats(stat_collector);
-}
+    set_profiling_mode(ProfilingMode::Rows);
+    let summaries = PROFILING_SUMMARIES.lock().unwrap().take().unwrap();
+    assert_eq!(
+        summaries[&("fft", 1 << 20, Device::Cpu)],
+        OpSummary {
+            count: 2,
+            total: ms(4),
+            min: ms(1),
+            max: ms(3),
+        }
+    );
 
-/// This perform recursive butterfly arithmetic
-pub fn recursive_butterfly_arithmetic<Scalar: Field, G: FftGroup<Scalar>>(
-    a: &mut [G],
-    n: usize,
-    twiddle_chunk: usize,
-    twiddles: &[Scalar],
-) {
-    if n == 2 {
-        let t = a[1];
-        a[1] = a[0];
-        a[0] += &t;
-        a[1] -= &t;
-    } else {
-        let (left, right) = a.split_at_mut(n / 2);
-        multicore::join(
-            || recursive_butterfly_arithmetic(left, n / 2, twiddle_chunk * 2, twiddles),
-            || recursive_butterfly_arithmetic(right, n / 2, twiddle_chunk * 2, twiddles),
-        );
+    let mut rows_written = 0;
+    record_profiled_op(("fft", 1 << 20, Device::Cpu), ms(1), || rows_written += 1);
+    assert_eq!(rows_written, 1);
+}
 
-        // case when twiddle factor is one
-        let (a, left) = left.split_at_mut(1);
-        let (b, right) = right.split_at_mut(1);
-        let t = b[0];
-        b[0] = a[0];
-        a[0] += &t;
-        b[0] -= &t;
+#[cfg(all(feature = "profiling", not(feature = "profiling-json")))]
+#[test]
+fn test_profiling_flush_guard() {
+    let profiling = ProfilingTestGuard::new("summary");
+    let ms = Duration::from_millis;
 
-        left.iter_mut()
-            .zip(right.iter_mut())
-            .enumerate()
-            .for_each(|(i, (a, b))| {
-                let mut t = *b;
-                t *= &twiddles[(i + 1) * twiddle_chunk];
-                *b = *a;
-                *a += &t;
-                *b -= &t;
-            });
+    set_profiling_mode(ProfilingMode::Histogram);
+    {
+        let _flush = ProfilingFlushGuard;
+        for duration in [ms(3), ms(1), ms(5)] {
+            record_profiled_op(("fft", 1 << 20, Device::Cpu), duration, || unreachable!());
+        }
+        record_profiled_op(("msm", 1 << 10, Device::Gpu), ms(2), || unreachable!());
     }
+
+    let filename = profiling.dir.join("profiling_summary.csv");
+    let summary = std::fs::read_to_string(&filename).unwrap();
+    assert_eq!(
+        summary.lines().collect::<Vec<_>>(),
+        [
+            "op,size,device,count,total_duration (ms),min_duration (ms),max_duration (ms)",
+            "fft,1048576,cpu,3,9,1,5",
+            "msm,1024,gpu,1,2,2,2",
+        ]
+    );
+
+    // The summaries were cleared, so flushing again writes nothing.
+    flush_profiling();
+    assert_eq!(std::fs::read_to_string(&filename).unwrap(), summary);
 }
 
-/// Convert coefficient bases group elements to lagrange basis by inverse FFT.
-pub fn g_to_lagrange<C: CurveAffine>(g_projective: Vec<C::Curve>, k: u32) -> Vec<C> {
-    let n_inv = C::Scalar::TWO_INV.pow_vartime([k as u64, 0, 0, 0]);
-    let mut omega_inv = C::Scalar::ROOT_OF_UNITY_INV;
-    for _ in k..C::Scalar::S {
-        omega_inv = omega_inv.square();
+#[test]
+fn test_multipoint_eval() {
+    for (poly_len, num_points) in [(0, 40), (10, 0), (10, 5), (50, 200), (300, 256), (7, 256)] {
+        let poly: Vec<Fp> = (0..poly_len).map(|_| Fp::random(OsRng)).collect();
+        let mut points: Vec<Fp> = (0..num_points).map(|_| Fp::random(OsRng)).collect();
+        if num_points > 1 {
+            points[1] = points[0];
+        }
+
+        let expected: Vec<Fp> = points.iter().map(|x| eval_polynomial(&poly, *x)).collect();
+        assert_eq!(multipoint_eval(&poly, &points), expected);
     }
+}
 
-    let mut g_lagrange_projective = g_projective;
-    best_fft(&mut g_lagrange_projective, omega_inv, k);
-    parallelize(&mut g_lagrange_projective, |g, _| {
-        for g in g.iter_mut() {
-            *g *= n_inv;
-        }
-    });
+#[test]
+fn test_cpu_multiexp_validated() {
+    use halo2curves::bn256::{Fq, Fr, G1Affine};
+    use rand_core::OsRng;
 
-    let mut g_lagrange = vec![C::identity(); 1 << k];
-    parallelize(&mut g_lagrange, |g_lagrange, starts| {
-        C::Curve::batch_normalize(
-            &g_lagrange_projective[starts..(starts + g_lagrange.len())],
-            g_lagrange,
-        );
-    });
+    let coeffs: Vec<Fr> = (0..64).map(|_| Fr::random(OsRng)).collect();
+    let mut bases: Vec<G1Affine> = (0..64)
+        .map(|_| (G1Affine::generator() * Fr::random(OsRng)).to_affine())
+        .collect();
+    assert_eq!(
+        cpu_multiexp_validated(&coeffs, &bases),
+        Ok(best_multiexp_cpu(&coeffs, &bases))
+    );
 
-    g_lagrange
+    for index in [40, 17] {
+        bases[index].y += Fq::one();
+    }
+    assert_eq!(cpu_multiexp_validated(&coeffs, &bases), Err(17));
 }
 
-/// This evaluates a provided polynomial (in coefficient form) at `point`.
-pub fn eval_polynomial<F: Field>(poly: &[F], point: F) -> F {
-    fn evaluate<F: Field>(poly: &[F], point: F) -> F {
-        poly.iter()
-            .rev()
-            .fold(F::ZERO, |acc, coeff| acc * point + coeff)
-    }
-    let n = poly.len();
-    let num_threads = multicore::current_num_threads();
-    if n * 2 < num_threads {
-        evaluate(poly, point)
-    } else {
-        let chunk_size = (n + num_threads - 1) / num_threads;
-        let mut parts = vec![F::ZERO; num_threads];
-        multicore::scope(|scope| {
-            for (chunk_idx, (out, poly)) in
-                parts.chunks_mut(1).zip(poly.chunks(chunk_size)).enumerate()
-            {
-                scope.spawn(move |_| {
-                    let start = chunk_idx * chunk_size;
-                    out[0] = evaluate(poly, point) * point.pow_vartime([start as u64, 0, 0, 0]);
-                });
+#[test]
+fn test_parallelize_with_rng() {
+    let sample = |seed| {
+        let mut points = vec![Fp::ZERO; 1000];
+        parallelize_with_rng(&mut points, seed, |points, _, rng| {
+            for point in points.iter_mut() {
+                *point = Fp::random(&mut *rng);
             }
         });
-        parts.iter().fold(F::ZERO, |acc, coeff| acc + coeff)
+        points
+    };
+
+    let points = sample([3; 32]);
+    assert_eq!(points, sample([3; 32]));
+    assert_ne!(points, sample([4; 32]));
+    // Chunks use different RNGs, so no two points coincide.
+    for (i, point) in points.iter().enumerate() {
+        assert!(!points[i + 1..].contains(point));
     }
 }
 
-/// This computes the inner product of two vectors `a` and `b`.
-///
-/// This function will panic if the two vectors are not the same size.
-pub fn compute_inner_product<F: Field>(a: &[F], b: &[F]) -> F {
-    // TODO: parallelize?
-    assert_eq!(a.len(), b.len());
-
-    let mut acc = F::ZERO;
-    for (a, b) in a.iter().zip(b.iter()) {
-        acc += (*a) * (*b);
+#[test]
+fn test_eval_polynomial_ref() {
+    let point = Fp::random(OsRng);
+    for len in [0, 1, 2, 100, 1000] {
+        let poly: Vec<Fp> = (0..len).map(|_| Fp::random(OsRng)).collect();
+        assert_eq!(eval_polynomial_ref(&poly, &point), eval_polynomial(&poly, point));
+        assert_eq!(eval_polynomial_ref(&poly, &point), serial::eval_polynomial(&poly, point));
     }
-
-    acc
 }
 
-/// Divides polynomial `a` in `X` by `X - b` with
-/// no remainder.
-pub fn kate_division<'a, F: Field, I: IntoIterator<Item = &'a F>>(a: I, mut b: F) -> Vec<F>
-where
-    I::IntoIter: DoubleEndedIterator + ExactSizeIterator,
-{
-    b = -b;
-    let a = a.into_iter();
+#[test]
+fn test_poly_add_sub_scale_neg() {
+    let random_poly = |len| -> Vec<Fp> { (0..len).map(|_| Fp::random(OsRng)).collect() };
+    let scale = Fp::random(OsRng);
 
-    let mut q = vec![F::ZERO; a.len() - 1];
+    // Lengths on both sides of the threshold for running in parallel.
+    for (a_len, b_len) in [(0, 0), (0, 5), (7, 3), (3, 7), (1500, 40), (40, 1500), (2000, 2000)] {
+        let (a, b) = (random_poly(a_len), random_poly(b_len));
+        let len = std::cmp::max(a_len, b_len);
+        let coeff = |poly: &[Fp], i: usize| poly.get(i).copied().unwrap_or(Fp::ZERO);
 
-    let mut tmp = F::ZERO;
-    for (q, r) in q.iter_mut().rev().zip(a.rev()) {
-        let mut lead_coeff = *r;
-        lead_coeff.sub_assign(&tmp);
-        *q = lead_coeff;
-        tmp = lead_coeff;
-        tmp.mul_assign(&b);
-    }
+        let sum: Vec<Fp> = (0..len).map(|i| coeff(&a, i) + coeff(&b, i)).collect();
+        assert_eq!(poly_add(&a, &b), sum);
+        assert_eq!(poly_add(&b, &a), sum);
+        let difference: Vec<Fp> = (0..len).map(|i| coeff(&a, i) - coeff(&b, i)).collect();
+        assert_eq!(poly_sub(&a, &b), difference);
+        assert_eq!(poly_sub(&b, &a), poly_neg(&difference));
 
-    q
+        assert_eq!(poly_scale(&a, scale), a.iter().map(|a| *a * scale).collect::<Vec<_>>());
+        assert_eq!(poly_neg(&a), a.iter().map(|a| -*a).collect::<Vec<_>>());
+    }
 }
 
-/// This utility function will parallelize an operation that is to be
-/// performed over a mutable slice.
-pub fn parallelize<T: Send, F: Fn(&mut [T], usize) + Send + Sync + Clone>(v: &mut [T], f: F) {
-    // Algorithm rationale:
-    //
-    // Using the stdlib `chunks_mut` will lead to severe load imbalance.
-    // From https://github.com/rust-lang/rust/blob/e94bda3/library/core/src/slice/iter.rs#L1607-L1637
-    // if the division is not exact, the last chunk will be the remainder.
-    //
-    // Dividing 40 items on 12 threads will lead to a chunk size of 40/12 = 3,
-    // There will be a 13 chunks of size 3 and 1 of size 1 distributed on 12 threads.
-    // This leads to 1 thread working on 6 iterations, 1 on 4 iterations and 10 on 3 iterations,
-    // a load imbalance of 2x.
-    //
-    // Instead we can divide work into chunks of size
-    // 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3 = 4*4 + 3*8 = 40
-    //
-    // This would lead to a 6/4 = 1.5x speedup compared to naive chunks_mut
-    //
-    // See also OpenMP spec (page 60)
-    // http://www.openmp.org/mp-documents/openmp-4.5.pdf
-    // "When no chunk_size is specified, the iteration space is divided into chunks
-    // that are approximately equal in size, and at most one chunk is distributed to
-    // each thread. The size of the chunks is unspecified in this case."
-    // This implies chunks are the same size ±1
+#[test]
+fn test_dft_planner() {
+    let planner = DftPlanner::new().serial_max_log_n(3).radix4_log_n(4..=8);
+    let names: Vec<_> = (1..=10)
+        .map(|log_n| planner.plan::<Fp, Fp>(log_n).name())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "serial", "serial", "serial", "radix-4", "radix-2", "radix-4", "radix-2", "radix-4",
+            "radix-2", "radix-2"
+        ]
+    );
 
-    let f = &f;
-    let total_iters = v.len();
-    let num_threads = multicore::current_num_threads();
-    let base_chunk_size = total_iters / num_threads;
-    let cutoff_chunk_id = total_iters % num_threads;
-    let split_pos = cutoff_chunk_id * (base_chunk_size + 1);
-    let (v_hi, v_lo) = v.split_at_mut(split_pos);
+    for log_n in 1..=10 {
+        let omega = root_of_unity::<Fp>(log_n);
+        let a: Vec<Fp> = (0..1 << log_n).map(|_| Fp::random(OsRng)).collect();
+        let mut expected = a.clone();
+        best_fft(&mut expected, omega, log_n);
 
-    multicore::scope(|scope| {
-        // Skip special-case: number of iterations is cleanly divided by number of threads.
-        if cutoff_chunk_id != 0 {
-            for (chunk_id, chunk) in v_hi.chunks_exact_mut(base_chunk_size + 1).enumerate() {
-                let offset = chunk_id * (base_chunk_size + 1);
-                scope.spawn(move |_| f(chunk, offset));
-            }
-        }
-        // Skip special-case: less iterations than number of threads.
-        if base_chunk_size != 0 {
-            for (chunk_id, chunk) in v_lo.chunks_exact_mut(base_chunk_size).enumerate() {
-                let offset = split_pos + (chunk_id * base_chunk_size);
-                scope.spawn(move |_| f(chunk, offset));
-            }
+        let strategies: [Box<dyn DftStrategy<Fp, Fp>>; 3] = [
+            Box::new(SerialDft { log_n }),
+            Box::new(Radix2Dft { log_n }),
+            Box::new(Radix4Dft { log_n }),
+        ];
+        for strategy in strategies.iter() {
+            let mut b = a.clone();
+            strategy.transform(&mut b, omega);
+            assert_eq!(b, expected, "{} differs at log_n = {}", strategy.name(), log_n);
         }
-    });
+    }
 }
 
-fn log2_floor(num: usize) -> u32 {
-    assert!(num > 0);
+#[test]
+fn test_cancellable() {
+    let coeffs: Vec<Fp> = (0..1 << 12).map(|_| Fp::random(OsRng)).collect();
+    let bases: Vec<EqAffine> = (0..1 << 12).map(|_| Eq::random(OsRng).to_affine()).collect();
+    let expected = best_multiexp_cpu(&coeffs, &bases);
 
-    let mut pow = 0;
+    let cancel = AtomicBool::new(false);
+    assert_eq!(cpu_multiexp_cancellable(&coeffs, &bases, &cancel), Some(expected));
+    cancel.store(true, Ordering::Relaxed);
+    assert_eq!(cpu_multiexp_cancellable(&coeffs, &bases, &cancel), None);
 
-    while (1 << (pow + 1)) <= num {
-        pow += 1;
-    }
+    let log_n = 12;
+    let omega = root_of_unity::<Fp>(log_n);
+    let a: Vec<Fp> = (0..1 << log_n).map(|_| Fp::random(OsRng)).collect();
+    let mut expected = a.clone();
+    best_fft(&mut expected, omega, log_n);
+
+    let cancel = AtomicBool::new(false);
+    let mut b = a.clone();
+    assert!(cpu_fft_cancellable(&mut b, omega, log_n, &cancel));
+    assert_eq!(b, expected);
+    cancel.store(true, Ordering::Relaxed);
+    assert!(!cpu_fft_cancellable(&mut a.clone(), omega, log_n, &cancel));
 
-    pow
+    // The flag flips once a thread has processed its first window, and every
+    // thread has more than one.
+    let cancel = AtomicBool::new(false);
+    let cancel_mid_run = || cancel.store(true, Ordering::Relaxed);
+    assert_eq!(
+        multiexp_cancellable_with(&coeffs, &bases, &cancel, cancel_mid_run),
+        None
+    );
 }
 
-/// Returns coefficients of an n - 1 degree polynomial given a set of n points
-/// and their evaluations. This function will panic if two values in `points`
-/// are the same.
-pub fn lagrange_interpolate<F: Field>(points: &[F], evals: &[F]) -> Vec<F> {
-    assert_eq!(points.len(), evals.len());
-    if points.len() == 1 {
-        // Constant polynomial
-        vec![evals[0]]
-    } else {
-        let mut denoms = Vec::with_capacity(points.len());
-        for (j, x_j) in points.iter().enumerate() {
-            let mut denom = Vec::with_capacity(points.len() - 1);
-            for x_k in points
-                .iter()
-                .enumerate()
-                .filter(|&(k, _)| k != j)
-                .map(|a| a.1)
-            {
-                denom.push(*x_j - x_k);
-            }
-            denoms.push(denom);
-        }
-        // Compute (x_j - x_k)^(-1) for each j != i
-        denoms.iter_mut().flat_map(|v| v.iter_mut()).batch_invert();
+#[test]
+fn test_multiexp_serial_const() {
+    let coeffs: Vec<Fp> = (0..300).map(|_| Fp::random(OsRng)).collect();
+    let bases: Vec<EqAffine> = (0..300).map(|_| Eq::random(OsRng).to_affine()).collect();
+    let reprs = scalars_to_reprs_serial(&coeffs);
 
-        let mut final_poly = vec![F::ZERO; points.len()];
-        for (j, (denoms, eval)) in denoms.into_iter().zip(evals.iter()).enumerate() {
-            let mut tmp: Vec<F> = Vec::with_capacity(points.len());
-            let mut product = Vec::with_capacity(points.len() - 1);
-            tmp.push(F::ONE);
-            for (x_k, denom) in points
-                .iter()
-                .enumerate()
-                .filter(|&(k, _)| k != j)
-                .map(|a| a.1)
-                .zip(denoms.into_iter())
-            {
-                product.resize(tmp.len() + 1, F::ZERO);
-                for ((a, b), product) in tmp
-                    .iter()
-                    .chain(std::iter::once(&F::ZERO))
-                    .zip(std::iter::once(&F::ZERO).chain(tmp.iter()))
-                    .zip(product.iter_mut())
-                {
-                    *product = *a * (-denom * x_k) + *b * denom;
-                }
-                std::mem::swap(&mut tmp, &mut product);
-            }
-            assert_eq!(tmp.len(), points.len());
-            assert_eq!(product.len(), points.len() - 1);
-            for (final_coeff, interpolation_coeff) in final_poly.iter_mut().zip(tmp.into_iter()) {
-                *final_coeff += interpolation_coeff * eval;
-            }
-        }
-        final_poly
+    macro_rules! check {
+        ($($c:literal),*) => {$(
+            let mut expected = Eq::identity();
+            multiexp_serial(&reprs, &bases, &mut expected, $c);
+            assert_eq!(multiexp_serial_const_pub::<EqAffine, $c>(&coeffs, &bases), expected);
+        )*};
     }
+    check!(1, 3, 8, 12, 16);
 }
 
-pub(crate) fn evaluate_vanishing_polynomial<F: Field>(roots: &[F], z: F) -> F {
-    fn evaluate<F: Field>(roots: &[F], z: F) -> F {
-        roots.iter().fold(F::ONE, |acc, point| (z - point) * acc)
-    }
-    let n = roots.len();
-    let num_threads = multicore::current_num_threads();
-    if n * 2 < num_threads {
-        evaluate(roots, z)
-    } else {
-        let chunk_size = (n + num_threads - 1) / num_threads;
-        let mut parts = vec![F::ONE; num_threads];
-        multicore::scope(|scope| {
-            for (out, roots) in parts.chunks_mut(1).zip(roots.chunks(chunk_size)) {
-                scope.spawn(move |_| out[0] = evaluate(roots, z));
-            }
-        });
-        parts.iter().fold(F::ONE, |acc, part| acc * part)
-    }
+#[test]
+#[should_panic(expected = "window size 17 is out of range")]
+fn test_multiexp_serial_const_too_wide() {
+    let bases = [Eq::random(OsRng).to_affine()];
+    multiexp_serial_const_pub::<EqAffine, 17>(&[Fp::ONE], &bases);
 }
 
-pub(crate) fn powers<F: Field>(base: F) -> impl Iterator<Item = F> {
-    std::iter::successors(Some(F::ONE), move |power| Some(base * power))
-}
+#[test]
+fn test_vanishing_poly_coeffs() {
+    assert_eq!(vanishing_poly_coeffs::<Fp>(&[]), vec![Fp::ONE]);
 
-#[cfg(test)]
-use rand_core::OsRng;
+    let roots: Vec<Fp> = (0..300).map(|_| Fp::random(OsRng)).collect();
+    let coeffs = vanishing_poly_coeffs(&roots);
+    assert_eq!(coeffs.len(), roots.len() + 1);
+    assert_eq!(coeffs.last(), Some(&Fp::ONE));
+    for root in roots.iter() {
+        assert_eq!(eval_polynomial(&coeffs, *root), Fp::ZERO);
+    }
 
-#[cfg(test)]
-use crate::halo2curves::pasta::Fp;
+    let z = Fp::random(OsRng);
+    assert_eq!(eval_polynomial(&coeffs, z), vanishing_poly_eval(&roots, z));
+}
 
 #[test]
-fn test_lagrange_interpolate() {
-    let rng = OsRng;
+fn test_parallel_thresholds() {
+    // Only this test changes the thresholds, which never change results.
+    let num_threads = multicore::current_num_threads();
+    let coeffs: Vec<Fp> = (0..500).map(|_| Fp::random(OsRng)).collect();
+    let bases: Vec<EqAffine> = (0..500).map(|_| Eq::random(OsRng).to_affine()).collect();
+    let expected = multiexp_serial_pub(&coeffs, &bases);
 
-    let points = (0..5).map(|_| Fp::random(rng)).collect::<Vec<_>>();
-    let evals = (0..5).map(|_| Fp::random(rng)).collect::<Vec<_>>();
+    set_msm_parallel_threshold(1000);
+    assert!(!msm_is_parallel(500));
+    assert_eq!(best_multiexp_cpu(&coeffs, &bases), expected);
+    set_msm_parallel_threshold(1);
+    assert_eq!(msm_is_parallel(500), 500 > num_threads);
+    assert_eq!(best_multiexp_cpu(&coeffs, &bases), expected);
+    set_msm_parallel_threshold(0);
 
-    for coeffs in 0..5 {
-        let points = &points[0..coeffs];
-        let evals = &evals[0..coeffs];
+    let poly: Vec<Fp> = (0..500).map(|_| Fp::random(OsRng)).collect();
+    let point = Fp::random(OsRng);
+    let expected = serial::eval_polynomial(&poly, point);
+    for threshold in [1, 1000] {
+        set_eval_parallel_threshold(threshold);
+        assert_eq!(eval_is_serial(500), threshold > 500);
+        assert_eq!(eval_polynomial(&poly, point), expected);
+    }
+    set_eval_parallel_threshold(0);
+    assert_eq!(eval_is_serial(500), 1000 < num_threads);
+}
 
-        let poly = lagrange_interpolate(points, evals);
-        assert_eq!(poly.len(), points.len());
+#[test]
+fn test_convolve() {
+    for (log_n, a_len, b_len) in [(0, 1, 1), (3, 8, 8), (3, 5, 2), (5, 20, 30), (6, 0, 64)] {
+        let n = 1 << log_n;
+        let a: Vec<Fp> = (0..a_len).map(|_| Fp::random(OsRng)).collect();
+        let b: Vec<Fp> = (0..b_len).map(|_| Fp::random(OsRng)).collect();
 
-        for (point, eval) in points.iter().zip(evals) {
-            assert_eq!(eval_polynomial(&poly, *point), *eval);
+        let mut expected = vec![Fp::ZERO; n];
+        for (i, a) in a.iter().enumerate() {
+            for (j, b) in b.iter().enumerate() {
+                expected[(i + j) % n] += *a * b;
+            }
         }
+
+        let omega = root_of_unity::<Fp>(log_n);
+        let mut result = a.clone();
+        convolve(&mut result, &b, omega, omega.invert().unwrap(), log_n);
+        assert_eq!(result, expected);
     }
 }
 
 #[test]
-fn test_best_fft() {
-    let k = 4; // Example value
-    let size = 1 << k;
+fn test_fft_bitreversed() {
+    for log_n in 0..=10 {
+        let omega = root_of_unity::<Fp>(log_n);
+        let a: Vec<Fp> = (0..1 << log_n).map(|_| Fp::random(OsRng)).collect();
 
-    // Generate random inputs
-    let mut a = (0..size).map(|_| Fp::random(OsRng)).collect::<Vec<_>>();
-    let omega = Fp::random(OsRng);
+        let mut expected = a.clone();
+        best_fft(&mut expected, omega, log_n);
+        let mut b = a.clone();
+        fft_bitreversed_output(&mut b, omega, log_n);
+        for (i, expected) in expected.iter().enumerate() {
+            assert_eq!(b[bitreverse(i, log_n as usize)], *expected);
+        }
 
-    // Run the FFT
-    best_fft(&mut a, omega, k);
+        ifft_bitreversed_input(&mut b, omega.invert().unwrap(), log_n);
+        assert_eq!(b, a);
+    }
+}
 
-    // Check that the result is as expected (this will depend on your expected outcome)
-    // For now, just assert that the length is as expected
-    assert_eq!(a.len(), size);
+#[test]
+fn test_fft_group() {
+    for log_n in 0..=6 {
+        let omega = root_of_unity::<Fp>(log_n);
+        let a: Vec<Eq> = (0..1 << log_n).map(|_| Eq::random(OsRng)).collect();
+
+        let mut expected = a.clone();
+        best_fft(&mut expected, omega, log_n);
+        let mut b = a.clone();
+        fft_group::<EqAffine>(&mut b, omega, log_n);
+        assert_eq!(b, expected);
+    }
+}
+
+#[test]
+fn test_compute_inner_product_compensated() {
+    for len in [0, 1, 7, 1000] {
+        let a: Vec<Fp> = (0..len).map(|_| Fp::random(OsRng)).collect();
+        let b: Vec<Fp> = (0..len).map(|_| Fp::random(OsRng)).collect();
+        assert_eq!(
+            compute_inner_product_compensated(&a, &b),
+            compute_inner_product(&a, &b)
+        );
+    }
 }
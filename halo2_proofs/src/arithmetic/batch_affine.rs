@@ -0,0 +1,174 @@
+//! Bucket accumulation with batched affine additions, used by
+//! [`multiexp_serial`](super::multiexp_serial) with the `simd-msm` feature.
+//!
+//! Adding two affine points takes a single field inversion and three
+//! multiplications. Performing all the additions of a round at once lets them
+//! share one inversion with Montgomery's trick, at the cost of three more
+//! multiplications each, which is cheaper than the mixed projective additions
+//! otherwise used to fill the buckets. The additions of a round are also
+//! independent of one another, and operate on contiguous arrays of field
+//! elements, which leaves room for the compiler to vectorize them.
+
+use group::ff::{BatchInvert, Field};
+
+use super::CurveAffine;
+
+/// An affine point, as its coordinates, or `None` for the identity.
+type Point<C> = Option<(<C as CurveAffine>::Base, <C as CurveAffine>::Base)>;
+
+/// Returns the sums of the bases falling in each bucket, given the `c`-bit
+/// window `digits` of their coefficients at one segment, as
+/// [`multiexp_serial`] would compute them. The `i`-th sum is that of the bases
+/// whose window is `i + 1`.
+///
+/// [`multiexp_serial`]: super::multiexp_serial
+pub(super) fn bucket_sums<C: CurveAffine>(digits: &[u32], bases: &[C], c: usize) -> Vec<C> {
+    let num_buckets = (1 << c) - 1;
+
+    // Sort the bases by bucket, so that each bucket is a contiguous range of
+    // `points`, starting at `starts[i]` and holding `lens[i]` points.
+    let mut lens = vec![0; num_buckets];
+    for &digit in digits.iter().filter(|&&digit| digit != 0) {
+        lens[digit as usize - 1] += 1;
+    }
+    let starts: Vec<usize> = lens
+        .iter()
+        .scan(0, |start, len| {
+            let bucket_start = *start;
+            *start += len;
+            Some(bucket_start)
+        })
+        .collect();
+    let mut points: Vec<Point<C>> = vec![None; lens.iter().sum()];
+    let mut next = starts.clone();
+    for (&digit, base) in digits.iter().zip(bases.iter()) {
+        if digit != 0 {
+            let bucket = digit as usize - 1;
+            points[next[bucket]] = to_point(base);
+            next[bucket] += 1;
+        }
+    }
+
+    // Each round adds adjacent pairs of points within every bucket, halving
+    // the number of points, until each bucket holds at most one.
+    let mut denominators = vec![];
+    loop {
+        denominators.clear();
+        for (&start, &len) in starts.iter().zip(lens.iter()) {
+            for pair in points[start..start + len].chunks_exact(2) {
+                denominators.push(denominator::<C>(&pair[0], &pair[1]));
+            }
+        }
+        if denominators.is_empty() {
+            break;
+        }
+        denominators.iter_mut().batch_invert();
+
+        let mut inverses = denominators.iter();
+        for (&start, len) in starts.iter().zip(lens.iter_mut()) {
+            // The sum of the pair at 2i and 2i + 1 is written at i, which has
+            // been read by then.
+            for i in 0..*len / 2 {
+                let inverse = inverses.next().unwrap();
+                points[start + i] =
+                    add::<C>(&points[start + 2 * i], &points[start + 2 * i + 1], inverse);
+            }
+            if *len % 2 == 1 {
+                points[start + *len / 2] = points[start + *len - 1];
+            }
+            *len = (*len + 1) / 2;
+        }
+    }
+
+    starts
+        .iter()
+        .zip(lens.iter())
+        .map(|(&start, &len)| match len {
+            0 => C::identity(),
+            _ => from_point(&points[start]),
+        })
+        .collect()
+}
+
+fn to_point<C: CurveAffine>(base: &C) -> Point<C> {
+    Option::from(base.coordinates()).map(|coordinates| (*coordinates.x(), *coordinates.y()))
+}
+
+fn from_point<C: CurveAffine>(point: &Point<C>) -> C {
+    match point {
+        Some((x, y)) => C::from_xy(*x, *y).unwrap(),
+        None => C::identity(),
+    }
+}
+
+/// Returns the value whose inverse [`add`] needs to add `p` and `q`: that of
+/// the chord or tangent slope. Additions that need no inversion, because one
+/// side or the result is the identity, use one.
+fn denominator<C: CurveAffine>(p: &Point<C>, q: &Point<C>) -> C::Base {
+    match (p, q) {
+        (Some((x1, _)), Some((x2, _))) if x1 != x2 => *x2 - x1,
+        (Some((_, y1)), Some((_, y2))) if y1 == y2 && !bool::from(y1.is_zero()) => y1.double(),
+        _ => C::Base::ONE,
+    }
+}
+
+/// Adds `p` and `q`, given the inverse of their [`denominator`].
+fn add<C: CurveAffine>(p: &Point<C>, q: &Point<C>, inverse: &C::Base) -> Point<C> {
+    let ((x1, y1), (x2, y2)) = match (p, q) {
+        (None, _) => return *q,
+        (_, None) => return *p,
+        (Some(p), Some(q)) => (p, q),
+    };
+
+    let lambda = if x1 != x2 {
+        (*y2 - y1) * inverse
+    } else if y1 == y2 && !bool::from(y1.is_zero()) {
+        let xx = x1.square();
+        (xx.double() + xx + C::a()) * inverse
+    } else {
+        // q = -p
+        return None;
+    };
+    let x3 = lambda.square() - x1 - x2;
+    let y3 = lambda * (*x1 - x3) - y1;
+    Some((x3, y3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bucket_sums;
+    use crate::arithmetic::{get_at, scalars_to_reprs, window_digits, CurveAffine};
+    use group::{ff::Field, Curve, Group};
+    use halo2curves::pasta::{Eq, EqAffine, Fq};
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_bucket_sums() {
+        let c = 3;
+        let p = Eq::random(OsRng).to_affine();
+        let mut bases: Vec<EqAffine> = (0..200).map(|_| Eq::random(OsRng).to_affine()).collect();
+        // Exercise doublings, cancellations and the identity.
+        bases.extend([p, p, p, -p, EqAffine::identity()]);
+        let coeffs: Vec<Fq> = (0..bases.len()).map(|_| Fq::random(OsRng)).collect();
+        let reprs = scalars_to_reprs(&coeffs);
+        let digits = window_digits::<Fq>(&reprs, c);
+
+        for (segment, digits) in digits.chunks(bases.len()).enumerate().take(4) {
+            let mut expected = vec![Eq::identity(); (1 << c) - 1];
+            for (repr, base) in reprs.iter().zip(bases.iter()) {
+                let window = get_at::<Fq>(segment, c, repr);
+                if window != 0 {
+                    expected[window - 1] += base;
+                }
+            }
+            let expected: Vec<EqAffine> = expected.iter().map(|sum| sum.to_affine()).collect();
+
+            assert_eq!(bucket_sums(digits, &bases, c), expected);
+        }
+
+        // With a single window value, all the special cases meet in one bucket.
+        let ones = [1; 4];
+        assert_eq!(bucket_sums(&ones, &[p, p, -p, EqAffine::identity()], 1), vec![p]);
+        assert_eq!(bucket_sums(&ones, &[p, p, p, p], 1), vec![(p + p + p + p).to_affine()]);
+    }
+}
@@ -0,0 +1,94 @@
+//! FFTs over polynomials stored in memory-mapped files, for polynomials that
+//! are too large to hold in memory.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::slice;
+
+use memmap2::MmapMut;
+
+use super::{bitreverse_permute, compute_twiddles, serial, Field};
+
+/// Performs the same FFT as [`best_fft`](super::best_fft) on the $n = 2^k$
+/// coefficients stored in the file at `path`, when provided `log_n` = $k$,
+/// writing the evaluations back to the file.
+///
+/// The file is memory-mapped, and the OS page cache decides which parts of it
+/// are resident. The transform uses the iterative butterfly rounds of
+/// [`serial::fft`], whose passes sweep the file in order, rather than the
+/// recursive parallel FFT, which would jump between distant pages.
+///
+/// The file must be exactly `n * size_of::<Scalar>()` bytes long, and hold the
+/// coefficients in the in-memory representation of `Scalar`, i.e. as if a
+/// `&[Scalar]` had been written out byte for byte on the same platform. This
+/// is not the `PrimeField::to_repr` encoding. The mapping starts on a page
+/// boundary, which satisfies the alignment of any field element type.
+///
+/// Returns an error if the file cannot be opened or mapped, or has the wrong
+/// length.
+///
+/// # Safety
+///
+/// Every `size_of::<Scalar>()` bytes of the file must be a valid `Scalar`,
+/// and the file must not be modified by anything else while this runs.
+pub unsafe fn fft_mmap<Scalar: Field>(path: &Path, omega: Scalar, log_n: u32) -> io::Result<()> {
+    let n = 1usize << log_n;
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let expected_len = n * mem::size_of::<Scalar>();
+    if file.metadata()?.len() != expected_len as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "expected {} bytes of coefficients for log_n = {}",
+                expected_len, log_n
+            ),
+        ));
+    }
+
+    let mut mmap = MmapMut::map_mut(&file)?;
+    debug_assert_eq!(mmap.as_ptr() as usize % mem::align_of::<Scalar>(), 0);
+    let a = slice::from_raw_parts_mut(mmap.as_mut_ptr() as *mut Scalar, n);
+
+    let twiddles = compute_twiddles(omega, log_n);
+    bitreverse_permute(a, log_n);
+    serial::butterfly_rounds(a, log_n, &twiddles, 1);
+
+    mmap.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fft_mmap;
+    use crate::arithmetic::best_fft;
+    use group::ff::{Field, PrimeField};
+    use halo2curves::pasta::Fp;
+    use rand_core::OsRng;
+    use std::{fs, mem, slice};
+
+    fn as_bytes(values: &[Fp]) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values))
+        }
+    }
+
+    #[test]
+    fn test_fft_mmap() {
+        let log_n = 12;
+        let omega = Fp::ROOT_OF_UNITY.pow_vartime([1 << (Fp::S - log_n), 0, 0, 0]);
+        let mut a: Vec<Fp> = (0..(1 << log_n)).map(|_| Fp::random(OsRng)).collect();
+
+        let path = std::env::temp_dir().join(format!("halo2_fft_mmap_{}", std::process::id()));
+        fs::write(&path, as_bytes(&a)).unwrap();
+
+        // A file of the wrong size is rejected.
+        assert!(unsafe { fft_mmap(&path, omega, log_n + 1) }.is_err());
+
+        unsafe { fft_mmap(&path, omega, log_n) }.unwrap();
+        best_fft(&mut a, omega, log_n);
+        let evals = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(evals, as_bytes(&a));
+    }
+}
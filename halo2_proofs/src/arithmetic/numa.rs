@@ -0,0 +1,133 @@
+//! NUMA topology discovery, and thread pools pinned to each NUMA node, used by
+//! [`parallelize_numa`](super::parallelize_numa).
+
+use std::fs;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::{parallelize, ThreadPool, ThreadPoolBuilder};
+
+/// A thread pool whose threads are pinned to the CPUs of one NUMA node.
+pub(super) struct NodePool {
+    pool: ThreadPool,
+    cpus: usize,
+}
+
+/// The pools of each NUMA node, built on first use. `Some(None)` records that
+/// NUMA-aware scheduling is unavailable.
+static NODE_POOLS: Mutex<Option<Option<Arc<Vec<NodePool>>>>> = Mutex::new(None);
+
+/// Returns one pool per NUMA node, or `None` if the machine has a single node
+/// or its topology cannot be read.
+pub(super) fn node_pools() -> Option<Arc<Vec<NodePool>>> {
+    NODE_POOLS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(build_node_pools)
+        .clone()
+}
+
+fn build_node_pools() -> Option<Arc<Vec<NodePool>>> {
+    let nodes = read_topology()?;
+    if nodes.len() < 2 {
+        return None;
+    }
+
+    let pools = nodes
+        .into_iter()
+        .map(|cpus| {
+            let num_cpus = cpus.len();
+            ThreadPoolBuilder::new()
+                .num_threads(num_cpus)
+                .start_handler(move |i| {
+                    core_affinity::set_for_current(core_affinity::CoreId { id: cpus[i] });
+                })
+                .build()
+                .ok()
+                .map(|pool| NodePool {
+                    pool,
+                    cpus: num_cpus,
+                })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Arc::new(pools))
+}
+
+/// Reads the CPUs of each NUMA node from sysfs. This is only available on
+/// Linux.
+fn read_topology() -> Option<Vec<Vec<usize>>> {
+    let mut nodes = vec![];
+    for entry in fs::read_dir("/sys/devices/system/node").ok()? {
+        let path = entry.ok()?.path();
+        let is_node = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("node"))
+            .map_or(false, |id| id.parse::<usize>().is_ok());
+        if is_node {
+            let cpus = parse_cpu_list(fs::read_to_string(path.join("cpulist")).ok()?.trim())?;
+            if !cpus.is_empty() {
+                nodes.push(cpus);
+            }
+        }
+    }
+    Some(nodes)
+}
+
+/// Parses a Linux CPU list, such as `0-3,8,10-11`.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Splits `v` into one contiguous region per node of `nodes`, sized by its
+/// number of CPUs, and runs [`parallelize`] over each region on the pool of
+/// its node. `f` receives offsets into the whole of `v`.
+pub(super) fn parallelize_on_nodes<T, F>(nodes: &[NodePool], v: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(&mut [T], usize) + Send + Sync + Clone,
+{
+    let total_cpus: usize = nodes.iter().map(|node| node.cpus).sum();
+    let len = v.len();
+
+    thread::scope(|scope| {
+        let mut rest = v;
+        let mut start = 0;
+        let mut cpus_before = 0;
+        for node in nodes {
+            cpus_before += node.cpus;
+            let end = len / total_cpus * cpus_before + len % total_cpus * cpus_before / total_cpus;
+            let (region, tail) = mem::take(&mut rest).split_at_mut(end - start);
+            rest = tail;
+
+            let f = f.clone();
+            let offset = start;
+            scope.spawn(move || {
+                node.pool
+                    .install(|| parallelize(region, move |chunk, i| f(chunk, offset + i)))
+            });
+            start = end;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cpu_list;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-3,8,10-11"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Single-threaded implementations of the core polynomial, FFT and
+//! multi-exponentiation routines.
+//!
+//! Nothing in this module touches the thread pool, the GPU, the clock or the
+//! filesystem, so its results do not depend on the number of available
+//! threads, and it never logs profiling data.
+
+use group::ff::{BatchInvert, Field};
+
+use super::FftGroupLocal;
+
+pub use super::multiexp_serial_pub as multiexp_serial;
+
+/// This evaluates a provided polynomial (in coefficient form) at `point`.
+pub fn eval_polynomial<F: Field>(poly: &[F], point: F) -> F {
+    poly.iter()
+        .rev()
+        .fold(F::ZERO, |acc, coeff| acc * point + coeff)
+}
+
+/// This computes the inner product of two vectors `a` and `b`.
+///
+/// This function will panic if the two vectors are not the same size.
+pub fn compute_inner_product<F: Field>(a: &[F], b: &[F]) -> F {
+    assert_eq!(a.len(), b.len());
+
+    let mut acc = F::ZERO;
+    for (a, b) in a.iter().zip(b.iter()) {
+        acc += (*a) * (*b);
+    }
+    acc
+}
+
+/// Divides polynomial `a` in `X` by `X - b` with
+/// no remainder.
+pub fn kate_division<'a, F: Field, I: IntoIterator<Item = &'a F>>(a: I, mut b: F) -> Vec<F>
+where
+    I::IntoIter: DoubleEndedIterator + ExactSizeIterator,
+{
+    b = -b;
+    let a = a.into_iter();
+
+    let mut q = vec![F::ZERO; a.len() - 1];
+
+    let mut tmp = F::ZERO;
+    for (q, r) in q.iter_mut().rev().zip(a.rev()) {
+        let mut lead_coeff = *r;
+        lead_coeff.sub_assign(&tmp);
+        *q = lead_coeff;
+        tmp = lead_coeff;
+        tmp.mul_assign(&b);
+    }
+
+    q
+}
+
+/// Returns coefficients of an n - 1 degree polynomial given a set of n points
+/// and their evaluations. This function will panic if two values in `points`
+/// are the same, naming the indices of the first such pair.
+pub fn lagrange_interpolate<F: Field>(points: &[F], evals: &[F]) -> Vec<F> {
+    assert_eq!(points.len(), evals.len());
+    if points.len() == 1 {
+        // Constant polynomial
+        vec![evals[0]]
+    } else {
+        let mut denoms = Vec::with_capacity(points.len());
+        for (j, x_j) in points.iter().enumerate() {
+            let mut denom = Vec::with_capacity(points.len() - 1);
+            for x_k in points
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != j)
+                .map(|a| a.1)
+            {
+                denom.push(*x_j - x_k);
+            }
+            denoms.push(denom);
+        }
+        // A repeated point shows up as a zero denominator.
+        if denoms.iter().flatten().any(|denom| bool::from(denom.is_zero())) {
+            duplicate_point_panic("lagrange_interpolate", points);
+        }
+        // Compute (x_j - x_k)^(-1) for each j != i
+        denoms.iter_mut().flat_map(|v| v.iter_mut()).batch_invert();
+
+        let mut final_poly = vec![F::ZERO; points.len()];
+        for (j, (denoms, eval)) in denoms.into_iter().zip(evals.iter()).enumerate() {
+            let mut tmp: Vec<F> = Vec::with_capacity(points.len());
+            let mut product = Vec::with_capacity(points.len() - 1);
+            tmp.push(F::ONE);
+            for (x_k, denom) in points
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != j)
+                .map(|a| a.1)
+                .zip(denoms.into_iter())
+            {
+                product.resize(tmp.len() + 1, F::ZERO);
+                for ((a, b), product) in tmp
+                    .iter()
+                    .chain(core::iter::once(&F::ZERO))
+                    .zip(core::iter::once(&F::ZERO).chain(tmp.iter()))
+                    .zip(product.iter_mut())
+                {
+                    *product = *a * (-denom * x_k) + *b * denom;
+                }
+                core::mem::swap(&mut tmp, &mut product);
+            }
+            assert_eq!(tmp.len(), points.len());
+            assert_eq!(product.len(), points.len() - 1);
+            for (final_coeff, interpolation_coeff) in final_poly.iter_mut().zip(tmp.into_iter()) {
+                *final_coeff += interpolation_coeff * eval;
+            }
+        }
+        final_poly
+    }
+}
+
+/// Panics on behalf of the interpolation function `caller`, naming the indices
+/// of the first pair of equal values in `points`. This is only called once a
+/// zero denominator has shown that there is such a pair, so that the quadratic
+/// search for it is never paid otherwise.
+#[cold]
+pub(super) fn duplicate_point_panic<F: Field>(caller: &str, points: &[F]) -> ! {
+    for (i, x_i) in points.iter().enumerate() {
+        if let Some(j) = points[i + 1..].iter().position(|x_j| x_j == x_i) {
+            panic!(
+                "{}: duplicate x-coordinate at indices {},{}",
+                caller,
+                i,
+                i + 1 + j
+            );
+        }
+    }
+    unreachable!("{}: zero denominator without duplicate points", caller)
+}
+
+/// Performs a radix-2 FFT on a vector of size $n = 2^k$, when provided
+/// `log_n` = $k$ and an element of multiplicative order $n$ called `omega`
+/// ($\omega$). The result is that the vector `a`, when interpreted as the
+/// coefficients of a polynomial of degree $n - 1$, is transformed into the
+/// evaluations of this polynomial at each of the $n$ distinct powers of
+/// $\omega$. This transformation is invertible by providing $\omega^{-1}$ in
+/// place of $\omega$ and dividing each resulting field element by $n$.
+///
+/// This produces the same output as [`best_fft`](super::best_fft).
+pub fn fft<Scalar: Field, G: FftGroupLocal<Scalar>>(a: &mut [G], omega: Scalar, log_n: u32) {
+    let twiddles = compute_twiddles(omega, log_n);
+    bitreverse_permute(a, log_n);
+    butterfly_rounds(a, log_n, &twiddles, 1);
+}
+
+/// Computes the twiddle factors $\omega^0, \omega^1, \dots, \omega^{n/2 - 1}$
+/// used by an FFT of size $n = 2^k$, when provided `log_n` = $k$.
+///
+/// This produces the same output as
+/// [`compute_twiddles`](super::compute_twiddles).
+pub fn compute_twiddles<F: Field>(omega: F, log_n: u32) -> Vec<F> {
+    let n = 1usize << log_n;
+    (0..(n / 2))
+        .scan(F::ONE, |w, _| {
+            let tw = *w;
+            *w *= &omega;
+            Some(tw)
+        })
+        .collect()
+}
+
+/// Returns `n` with its lowest `l` bits reversed, and its higher bits
+/// discarded.
+pub fn bitreverse(mut n: usize, l: usize) -> usize {
+    let mut r = 0;
+    for _ in 0..l {
+        r = (r << 1) | (n & 1);
+        n >>= 1;
+    }
+    r
+}
+
+/// Reorders `a`, of size $2^k$ when provided `log_n` = $k$, by swapping each
+/// element with the one at the bit-reversed index. This converts between
+/// natural and bit-reversed orderings, and is its own inverse.
+///
+/// This function will panic if `a.len()` is not $2^k$.
+pub fn bitreverse_permute<T>(a: &mut [T], log_n: u32) {
+    let n = a.len();
+    assert_eq!(n, 1 << log_n);
+
+    for k in 0..n {
+        let rk = bitreverse(k, log_n as usize);
+        if k < rk {
+            a.swap(rk, k);
+        }
+    }
+}
+
+/// Runs the `log_n` butterfly rounds of an FFT over the bit-reversed `a`, one
+/// round after another on the current thread.
+///
+/// `twiddles` holds the powers of an $\omega$ of order `a.len() * stride`, so
+/// that `a` can also be one of the sub-transforms of a larger FFT.
+pub(super) fn butterfly_rounds<Scalar: Field, G: FftGroupLocal<Scalar>>(
+    a: &mut [G],
+    log_n: u32,
+    twiddles: &[Scalar],
+    stride: usize,
+) {
+    let n = a.len();
+    let mut chunk = 2_usize;
+    let mut twiddle_chunk = n / 2 * stride;
+    for _ in 0..log_n {
+        a.chunks_mut(chunk).for_each(|coeffs| {
+            let (left, right) = coeffs.split_at_mut(chunk / 2);
+
+            // case when twiddle factor is one
+            let (a, left) = left.split_at_mut(1);
+            let (b, right) = right.split_at_mut(1);
+            let t = b[0];
+            b[0] = a[0];
+            a[0] += &t;
+            b[0] -= &t;
+
+            super::butterflies(left, right, twiddles, twiddle_chunk);
+        });
+        chunk *= 2;
+        twiddle_chunk /= 2;
+    }
+}
@@ -3,19 +3,53 @@ use icicle::{
     curves::bn254::{Point_BN254, ScalarField_BN254},
     test_bn254::commit_bn254,
 };
-use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
 
 pub use icicle::curves::bn254::PointAffineNoInfinity_BN254;
 use rustacuda::memory::CopyDestination;
 use rustacuda::prelude::*;
 
 pub use halo2curves::CurveAffine;
-use std::{env, mem};
+use rustacuda::error::CudaError;
+use std::{env, fmt, mem};
 
 static mut GPU_CONTEXT: Option<Context> = None;
 static mut GPU_G: Option<DeviceBuffer<PointAffineNoInfinity_BN254>> = None;
 static mut GPU_G_LAGRANGE: Option<DeviceBuffer<PointAffineNoInfinity_BN254>> = None;
 static GPU_INIT: Once = Once::new();
+static GPU_CONTEXT_INIT: Once = Once::new();
+static GPU_DEVICE: AtomicUsize = AtomicUsize::new(0);
+
+/// An error encountered while performing an MSM on the GPU.
+#[derive(Debug)]
+pub enum GpuError {
+    /// The curve is not supported by the GPU kernels.
+    UnsupportedCurve,
+    /// The bases have not been copied to the device with `init_gpu`.
+    NotInitialized,
+    /// The selected device index is not present.
+    DeviceOutOfRange(usize),
+    /// The CUDA driver returned an error.
+    Cuda(CudaError),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuError::UnsupportedCurve => write!(f, "curve is not supported on the GPU"),
+            GpuError::NotInitialized => write!(f, "GPU bases have not been initialized"),
+            GpuError::DeviceOutOfRange(index) => write!(f, "GPU device {} does not exist", index),
+            GpuError::Cuda(e) => write!(f, "CUDA error: {}", e),
+        }
+    }
+}
+
+impl From<CudaError> for GpuError {
+    fn from(e: CudaError) -> Self {
+        GpuError::Cuda(e)
+    }
+}
 
 pub fn should_use_cpu_msm(size: usize) -> bool {
     size <= (1
@@ -23,15 +57,63 @@ pub fn should_use_cpu_msm(size: usize) -> bool {
 }
 
 pub fn init_gpu<C: CurveAffine>(g: &[C], g_lagrange: &[C]) {
+    if !crate::arithmetic::gpu_supports_curve::<C>() {
+        return;
+    }
+
+    if !warm_up_gpu() {
+        return;
+    }
+
     unsafe {
         GPU_INIT.call_once(|| {
-            GPU_CONTEXT = Some(rustacuda::quick_init().unwrap());
             GPU_G = Some(copy_points_to_device(g));
             GPU_G_LAGRANGE = Some(copy_points_to_device(g_lagrange));
         });
     }
 }
 
+/// Creates the CUDA context once, and returns whether a GPU is available. A
+/// failure to find a GPU is cached, so that later calls return immediately.
+pub fn warm_up_gpu() -> bool {
+    unsafe {
+        GPU_CONTEXT_INIT.call_once(|| {
+            GPU_CONTEXT = match create_context(get_gpu_device()) {
+                Ok(context) => Some(context),
+                Err(e) => {
+                    log::warn!("Failed to initialize the GPU, falling back to CPU: {}", e);
+                    None
+                }
+            };
+        });
+        GPU_CONTEXT.is_some()
+    }
+}
+
+fn create_context(index: usize) -> Result<Context, GpuError> {
+    rustacuda::init(CudaFlags::empty())?;
+    if index >= Device::num_devices()? as usize {
+        return Err(GpuError::DeviceOutOfRange(index));
+    }
+    let device = Device::get_device(index as u32)?;
+
+    Ok(Context::create_and_push(
+        ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO,
+        device,
+    )?)
+}
+
+/// Selects the GPU used by the GPU kernels. This only takes effect if called
+/// before the GPU is first initialized.
+pub fn set_gpu_device(index: usize) {
+    GPU_DEVICE.store(index, Ordering::Relaxed);
+}
+
+/// Returns the index of the GPU used by the GPU kernels.
+pub fn get_gpu_device() -> usize {
+    GPU_DEVICE.load(Ordering::Relaxed)
+}
+
 fn u32_from_u8(u8_arr: &[u8; 32]) -> [u32; 8] {
     let mut t = [0u32; 8];
     for i in 0..8 {
@@ -56,46 +138,45 @@ fn is_infinity_point(point: Point_BN254) -> bool {
     point.z.s.eq(&inf_point.z.s)
 }
 
-fn icicle_scalars_from_c<C: CurveAffine>(coeffs: &[C::Scalar]) -> Vec<ScalarField_BN254> {
-    let _coeffs = [Arc::new(
-        coeffs.iter().map(|x| x.to_repr()).collect::<Vec<_>>(),
-    )];
+/// Returns the little-endian `u32` limbs of a 32-byte field element encoding.
+fn limbs_from_repr(repr: &[u8]) -> [u32; 8] {
+    u32_from_u8(repr.try_into().expect("BN254 field elements are 32 bytes"))
+}
 
-    let _coeffs: &Arc<Vec<[u32; 8]>> = unsafe { mem::transmute(&_coeffs) };
-    _coeffs
+// Each element is converted straight into its device representation, without
+// collecting the intermediate byte encodings, which would be a second host
+// copy of the whole input.
+fn icicle_scalars_from_c<C: CurveAffine>(coeffs: &[C::Scalar]) -> Vec<ScalarField_BN254> {
+    coeffs
         .iter()
-        .map(|x| ScalarField_BN254::from_limbs(x))
-        .collect::<Vec<_>>()
+        .map(|x| ScalarField_BN254::from_limbs(&limbs_from_repr(x.to_repr().as_ref())))
+        .collect()
 }
 
 pub fn copy_scalars_to_device<C: CurveAffine>(
     coeffs: &[C::Scalar],
 ) -> DeviceBuffer<ScalarField_BN254> {
+    try_copy_scalars_to_device::<C>(coeffs).unwrap()
+}
+
+pub fn try_copy_scalars_to_device<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+) -> Result<DeviceBuffer<ScalarField_BN254>, GpuError> {
     let scalars = icicle_scalars_from_c::<C>(coeffs);
 
-    DeviceBuffer::from_slice(scalars.as_slice()).unwrap()
+    Ok(DeviceBuffer::from_slice(scalars.as_slice())?)
 }
 
 fn icicle_points_from_c<C: CurveAffine>(bases: &[C]) -> Vec<PointAffineNoInfinity_BN254> {
-    let _bases = [Arc::new(
-        bases
-            .iter()
-            .map(|p| {
-                let coordinates = p.coordinates().unwrap();
-                [coordinates.x().to_repr(), coordinates.y().to_repr()]
-            })
-            .collect::<Vec<_>>(),
-    )];
-
-    let _bases: &Arc<Vec<[[u8; 32]; 2]>> = unsafe { mem::transmute(&_bases) };
-    _bases
+    bases
         .iter()
-        .map(|x| {
-            let tx = u32_from_u8(&x[0]);
-            let ty = u32_from_u8(&x[1]);
+        .map(|p| {
+            let coordinates = p.coordinates().unwrap();
+            let tx = limbs_from_repr(coordinates.x().to_repr().as_ref());
+            let ty = limbs_from_repr(coordinates.y().to_repr().as_ref());
             PointAffineNoInfinity_BN254::from_limbs(&tx, &ty)
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
 pub fn copy_points_to_device<C: CurveAffine>(
@@ -124,23 +205,47 @@ fn c_from_icicle_point<C: CurveAffine>(commit_res: Point_BN254) -> C::Curve {
     return affine.to_curve();
 }
 
+/// Performs an MSM over `bases`, which are copied to the device for it, rather
+/// than over the bases copied by `init_gpu`.
+pub fn try_multiexp_with_bases<C: CurveAffine>(
+    coeffs: &[C::Scalar],
+    bases: &[C],
+) -> Result<C::Curve, GpuError> {
+    let mut scalars = try_copy_scalars_to_device::<C>(coeffs)?;
+    let mut points = DeviceBuffer::from_slice(icicle_points_from_c(bases).as_slice())?;
+
+    let d_commit_result = commit_bn254(&mut points, &mut scalars, 10);
+
+    let mut h_commit_result = Point_BN254::zero();
+    d_commit_result.copy_to(&mut h_commit_result)?;
+
+    Ok(c_from_icicle_point::<C>(h_commit_result))
+}
+
 pub fn multiexp_on_device<C: CurveAffine>(
-    mut coeffs: DeviceBuffer<ScalarField_BN254>,
+    coeffs: DeviceBuffer<ScalarField_BN254>,
     is_lagrange: bool,
 ) -> C::Curve {
+    try_multiexp_on_device::<C>(coeffs, is_lagrange).unwrap()
+}
+
+pub fn try_multiexp_on_device<C: CurveAffine>(
+    mut coeffs: DeviceBuffer<ScalarField_BN254>,
+    is_lagrange: bool,
+) -> Result<C::Curve, GpuError> {
     let base_ptr: &mut DeviceBuffer<PointAffineNoInfinity_BN254>;
     unsafe {
         if is_lagrange {
-            base_ptr = GPU_G_LAGRANGE.as_mut().unwrap();
+            base_ptr = GPU_G_LAGRANGE.as_mut().ok_or(GpuError::NotInitialized)?;
         } else {
-            base_ptr = GPU_G.as_mut().unwrap();
+            base_ptr = GPU_G.as_mut().ok_or(GpuError::NotInitialized)?;
         };
     }
 
     let d_commit_result = commit_bn254(base_ptr, &mut coeffs, 10);
 
     let mut h_commit_result = Point_BN254::zero();
-    d_commit_result.copy_to(&mut h_commit_result).unwrap();
+    d_commit_result.copy_to(&mut h_commit_result)?;
 
-    c_from_icicle_point::<C>(h_commit_result)
+    Ok(c_from_icicle_point::<C>(h_commit_result))
 }
@@ -33,6 +33,8 @@ pub use halo2curves;
 mod multicore;
 pub mod plonk;
 pub mod poly;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod transcript;
 
 pub mod dev;
@@ -3,7 +3,7 @@ pub use maybe_rayon::{
     iter::{IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator},
     join, scope,
     slice::ParallelSliceMut,
-    Scope,
+    Scope, ThreadPool, ThreadPoolBuilder,
 };
 
 pub trait TryFoldAndReduce<T, E> {
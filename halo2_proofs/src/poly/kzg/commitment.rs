@@ -1,7 +1,7 @@
 use crate::arithmetic::{best_multiexp_cpu, g_to_lagrange, parallelize};
 
 #[cfg(feature = "icicle_gpu")]
-use crate::arithmetic::best_multiexp_gpu;
+use crate::arithmetic::{best_multiexp_gpu_or_cpu, gpu_supports_curve};
 #[cfg(feature = "icicle_gpu")]
 use crate::icicle;
 #[cfg(feature = "icicle_gpu")]
@@ -341,8 +341,11 @@ where
         assert!(bases.len() >= size);
 
         #[cfg(feature = "icicle_gpu")]
-        if env::var("ENABLE_ICICLE_GPU").is_ok() && !icicle::should_use_cpu_msm(size) {
-            best_multiexp_gpu::<E::G1Affine>(&scalars, true)
+        if env::var("ENABLE_ICICLE_GPU").is_ok()
+            && gpu_supports_curve::<E::G1Affine>()
+            && !icicle::should_use_cpu_msm(size)
+        {
+            best_multiexp_gpu_or_cpu(&scalars, &bases[0..size], true)
         } else {
             best_multiexp_cpu(&scalars, &bases[0..size])
         }
@@ -394,8 +397,11 @@ where
         assert!(bases.len() >= size);
 
         #[cfg(feature = "icicle_gpu")]
-        if env::var("ENABLE_ICICLE_GPU").is_ok() && !icicle::should_use_cpu_msm(size) {
-            best_multiexp_gpu::<E::G1Affine>(&scalars, false)
+        if env::var("ENABLE_ICICLE_GPU").is_ok()
+            && gpu_supports_curve::<E::G1Affine>()
+            && !icicle::should_use_cpu_msm(size)
+        {
+            best_multiexp_gpu_or_cpu(&scalars, &bases[0..size], false)
         } else {
             best_multiexp_cpu(&scalars, &bases[0..size])
         }
@@ -0,0 +1,52 @@
+//! Helpers for building inputs to MSMs and FFTs in tests and benchmarks.
+
+use group::{ff::Field, prime::PrimeCurveAffine, Curve, Group};
+use rand_core::RngCore;
+
+use crate::arithmetic::CurveAffine;
+
+/// Samples `n` uniformly random points and returns them in affine form. The
+/// points are drawn in the projective group and then normalized together, so
+/// every returned point lies on the curve.
+///
+/// Passing a seeded `rng` makes the output deterministic across runs.
+pub fn random_bases<C: CurveAffine>(n: usize, rng: &mut impl RngCore) -> Vec<C> {
+    let projective = (0..n)
+        .map(|_| C::Curve::random(&mut *rng))
+        .collect::<Vec<_>>();
+    let mut bases = vec![C::identity(); n];
+    C::Curve::batch_normalize(&projective, &mut bases);
+    bases
+}
+
+/// Samples `n` uniformly random field elements.
+///
+/// Passing a seeded `rng` makes the output deterministic across runs.
+pub fn random_scalars<F: Field>(n: usize, rng: &mut impl RngCore) -> Vec<F> {
+    (0..n).map(|_| F::random(&mut *rng)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_bases, random_scalars};
+    use crate::arithmetic::CurveAffine;
+    use halo2curves::pasta::{EqAffine, Fp};
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn test_random_bases_on_curve_and_seeded() {
+        let bases = random_bases::<EqAffine>(64, &mut ChaCha20Rng::seed_from_u64(7));
+        assert_eq!(bases.len(), 64);
+        for base in bases.iter() {
+            assert!(bool::from(base.is_on_curve()));
+        }
+
+        let again = random_bases::<EqAffine>(64, &mut ChaCha20Rng::seed_from_u64(7));
+        assert_eq!(bases, again);
+
+        let scalars = random_scalars::<Fp>(64, &mut ChaCha20Rng::seed_from_u64(7));
+        let again = random_scalars::<Fp>(64, &mut ChaCha20Rng::seed_from_u64(7));
+        assert_eq!(scalars, again);
+    }
+}